@@ -5,6 +5,7 @@ mod camera;
 mod color;
 mod player;
 mod collision;
+mod profiler;
 mod world;
 
 use chunk::Chunk;
@@ -17,8 +18,9 @@ use crate::game::world::World;
 
 use self::input_handler::InputMap;
 use self::player::Player;
+use self::profiler::Phase;
 use self::renderer::Renderer;
-use self::renderer::mesh::Mesh;
+use self::renderer::arena::ChunkMesh;
 use cgmath::{MetricSpace, Vector3};
 
 const RENDER_DISTANCE: u32 = 8;
@@ -28,6 +30,13 @@ const FIXED_UPDATES_PER_SECOND: u32 = 120;
 
 
 const CHUNKS_GEN_PER_FRAME: u32 = 16;
+// How many finished chunk meshes get uploaded each frame, the rest wait for the next one.
+const CHUNKS_MESHED_PER_FRAME: u32 = 32;
+// How often the frame timings in the window title are refreshed, in seconds.
+const PROFILER_TITLE_INTERVAL: f32 = 0.5;
+// The blocks the number keys pick between for placing.
+const HOTBAR: [&str; 9] = ["stone", "dirt", "grass", "log", "glass", "leaves", "glowstone", "crystal", "furnace"];
+const HOTBAR_KEYS: [Key; 9] = [Key::Key1, Key::Key2, Key::Key3, Key::Key4, Key::Key5, Key::Key6, Key::Key7, Key::Key8, Key::Key9];
 
 struct Game {
 
@@ -35,6 +44,7 @@ struct Game {
     time_since_last_tick: f32,
     ticks_per_second: f32,
     previous_frame_time: std::time::Instant,
+    time_since_title_update: f32,
 
     world: World,
     renderer: Renderer,
@@ -45,6 +55,8 @@ struct Game {
     is_minimized: bool,
     is_paused: bool,
 
+    // Index into HOTBAR.
+    selected_block: usize,
 }
 
 
@@ -52,6 +64,14 @@ struct Game {
 // Executes consistently every x amount of time.
 fn fixed_update(game: &mut Game) {
     game.player.update(&mut game.input, &game.world);
+
+    // Switches between smooth and flat lighting, remeshing every chunk to show the difference.
+    if game.input.get_key(Key::F6).just_pressed {
+        game.world.smooth_lighting = !game.world.smooth_lighting;
+        for chunk in game.world.chunks.values_mut() {
+            chunk.should_regen_mesh = true;
+        }
+    }
 }
 
 // Executes every time before a frame is rendered.
@@ -64,7 +84,45 @@ fn update(game: &mut Game) {
     );
     let icam_pos: Vector3<i32> = Vector3::new(fcam_pos.x as i32, fcam_pos.y as i32, fcam_pos.z as i32);
 
+    // The far plane has to reach the far side of the furthest regions of terrain.
+    let furthest = game.world.view_distance() + world::lod::LodRegion::size(world::lod::LOD_LEVELS as u32) as f32;
+    game.player.camera.far = furthest * chunk::CHUNK_SIZE as f32;
+
     game.world.update(&mut game.renderer, &game.player.camera, game.delta);
+
+    for (i, key) in HOTBAR_KEYS.iter().enumerate() {
+        if game.input.get_key(*key).just_pressed {
+            game.selected_block = i;
+        }
+    }
+
+    // Breaks the block the camera is looking at, places the selected block against the face it's looking at,
+    // or with E lights or puts out the block it's looking at, like a furnace.
+    let (lmb, rmb, toggle) = (game.input.mouse.lmb.just_pressed, game.input.mouse.rmb.just_pressed, game.input.get_key(Key::E).just_pressed);
+    if lmb || rmb || toggle {
+        let (forward, _, _) = game.player.camera.get_headings();
+        let target = game.world.raycast(game.player.camera.position, forward, player::REACH).map(|hit| (hit.position, hit.normal, hit.state, hit.block.fluid));
+        let block_list = game.world.block_list.clone();
+
+        match target {
+            Some((position, _, _, _)) if lmb => { game.world.set_block_at(position, 0); },
+            // Nothing gets placed against fluids. A zero normal means the camera is inside the block, with no face to place against.
+            Some((position, normal, _, fluid)) if rmb && !fluid && normal != Vector3::new(0, 0, 0) => {
+                if let Some(block) = block_list.get_block(HOTBAR[game.selected_block]) {
+                    game.world.set_block_at(position + normal, block.get_placement_state(normal, forward));
+                }
+            },
+            Some((position, _, state, _)) if toggle => {
+                let lit = block_list.get_state_value(state, "lit") == Some("true");
+                if let Some(toggled) = block_list.with_value(state, "lit", if lit { "false" } else { "true" }) {
+                    game.world.set_block_at(position, toggled);
+                }
+            },
+            _ => (),
+        }
+    }
+
+    let _queueing = profiler::scope(Phase::Queueing);
     game.world.generate_chunk(&icam_pos, &fcam_pos);
     
     let mut chunks_to_loop: Vec<Vector3<i32>> = Vec::new();
@@ -104,22 +162,60 @@ fn update(game: &mut Game) {
 fn render(game: &mut Game) {
 
     if !game.is_minimized {
-        let mut pool: Vec<&Mesh> = Vec::new();
+        let culling = profiler::scope(Phase::Culling);
+        let mut pool: Vec<&ChunkMesh> = Vec::new();
+        let mut translucent_pool: Vec<(f32, &ChunkMesh)> = Vec::new();
+        let mut shadow_pool: Vec<&ChunkMesh> = Vec::new();
         let chunk_diag: f32 = f32::sqrt((chunk::CHUNK_SIZE as f32 * chunk::CHUNK_SIZE as f32) + (chunk::CHUNK_SIZE as f32 * chunk::CHUNK_SIZE as f32));
+        // Chunks hidden behind terrain are left out, caves especially.
+        let visible = game.world.visible_chunks(&game.player.camera);
+
+        for (at, chunk) in &game.world.chunks {
 
-        for (_at, chunk) in &game.world.chunks {
+            // Chunks out of view can still cast shadows into it.
+            if let Some(mesh) = &chunk.mesh {
+                if chunk.get_world_position().distance(game.player.camera.position) < renderer::shadow::SHADOW_DISTANCE + renderer::shadow::CASTER_MARGIN {
+                    shadow_pool.push(mesh);
+                }
+            }
 
-            if game.player.camera.frustum.sphere_intersection(chunk.get_world_position(), chunk_diag * 0.75) {
+            // Chunks further out are drawn as part of a region instead.
+            if !game.world.is_full_detail(*at, &game.player.camera) { continue; }
+
+            if visible.contains(at) && game.player.camera.frustum.sphere_intersection(chunk.get_world_position(), chunk_diag * 0.75) {
                 match &chunk.mesh {
                     Some(mesh) => {
                         pool.push(mesh);
                     }
                     None => ()
                 }
+
+                if let Some(mesh) = &chunk.translucent_mesh {
+                    translucent_pool.push((chunk.get_world_position().distance(game.player.camera.position), mesh));
+                }
             }
         }
-        
-        game.renderer.render(&game.player.camera, &pool, game.world.sky_color);
+
+        // Far off terrain. It's past where shadows reach, so it's left out of those.
+        for region in game.world.lod_regions.values() {
+            let center = world::lod::LodRegion::center(region.level, region.position) * chunk::CHUNK_SIZE as f32;
+            if !game.player.camera.frustum.sphere_intersection(center, world::lod::LodRegion::radius(region.level)) { continue; }
+
+            if let Some(mesh) = &region.mesh {
+                pool.push(mesh);
+            }
+            if let Some(mesh) = &region.translucent_mesh {
+                translucent_pool.push((center.distance(game.player.camera.position), mesh));
+            }
+        }
+
+        // Translucent chunks are blended, so they're drawn back to front.
+        translucent_pool.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let translucent_pool: Vec<&ChunkMesh> = translucent_pool.into_iter().map(|(_, mesh)| mesh).collect();
+        drop(culling);
+
+        let _render = profiler::scope(Phase::Render);
+        game.renderer.render(&game.player.camera, &pool, &translucent_pool, &shadow_pool, game.world.fog_color);
     }
 }
 
@@ -164,7 +260,8 @@ pub fn run() {
 
     // Setting up the game struct.
     let _world = World::new();
-    let _renderer = block_on(renderer::Renderer::new(&window));
+    let mut _renderer = block_on(renderer::Renderer::new(&window));
+    _renderer.clouds.build_mesh(&_renderer.device, &_world.generate_cloud_map());
     let _input = input_handler::InputMap::new();
     let _player = player::Player::new(&_renderer);
     
@@ -177,10 +274,13 @@ pub fn run() {
         time_since_last_tick: 0.0,
         ticks_per_second: 1.0 / FIXED_UPDATES_PER_SECOND as f32,
         previous_frame_time: std::time::Instant::now(),
+        time_since_title_update: 0.0,
         
         is_focused: true,
         is_minimized: false,
         is_paused: false,
+
+        selected_block: 6,
     };
     println!("seed: {}", game.world.seed);
 
@@ -224,6 +324,9 @@ pub fn run() {
 
             Event::MainEventsCleared => {
 
+                // Everything since the last time through here, the last frame's render included, counts as one frame.
+                profiler::end_frame();
+
                 // Calculating delta time.
                 let now = std::time::Instant::now();
                 game.delta = (now - game.previous_frame_time).as_secs_f32();
@@ -236,8 +339,33 @@ pub fn run() {
                     game.is_paused = !game.is_paused;
                 }
 
+                game.time_since_title_update += game.delta;
+                if game.time_since_title_update > PROFILER_TITLE_INTERVAL {
+                    game.time_since_title_update = 0.0;
+                    // The block being looked at goes in the title too, as there's no on-screen text yet.
+                    let (forward, _, _) = game.player.camera.get_headings();
+                    let looking_at = match game.world.raycast(game.player.camera.position, forward, player::REACH) {
+                        Some(hit) => format!("{} at {} {} {}, {:.1} away", game.world.block_list.describe_state(hit.state), hit.position.x, hit.position.y, hit.position.z, hit.distance),
+                        None => "nothing".to_string(),
+                    };
+                    window.set_title(&format!("Block Engine - {} - looking at {} - placing {}", profiler::with(|profiler| profiler.summary()), looking_at, HOTBAR[game.selected_block]));
+                }
+
+                // Writes out the timings of the last few hundred frames.
+                if game.input.get_key(Key::F8).just_pressed {
+                    profiler::with(|profiler| {
+                        print!("{}", profiler.report());
+                        let written = profiler.export_csv("profile.csv").and_then(|_| profiler.export_trace("profile_trace.json"));
+                        match written {
+                            Ok(_) => println!("Frame timings written to profile.csv and profile_trace.json"),
+                            Err(error) => println!("Couldn't write frame timings: {:?}", error),
+                        }
+                    });
+                }
+
                 while game.time_since_last_tick > game.ticks_per_second {
                     game.time_since_last_tick -= game.ticks_per_second;
+                    let _fixed_update = profiler::scope(Phase::FixedUpdate);
                     fixed_update(&mut game);
                 }
 
@@ -1,23 +1,114 @@
 pub mod block;
 
-use block::Block;
-use super::{World, renderer::mesh::Mesh};
-use super::renderer::vertex::Vertex;
+use std::sync::Arc;
+
+use block::{Block, BlockList, BlockTable, RenderType, SideTexture};
+use super::{color::Color, renderer::arena::ChunkMesh, world::lighting::MAX_LIGHT};
+use super::renderer::vertex::ChunkVertex;
+use bracket_noise::prelude::FastNoise;
 use cgmath::Vector3;
 
 pub const CHUNK_SIZE: usize = 16;
 
 pub const GRID_MAX: usize = CHUNK_SIZE - 1;
 
+// Below this everything is stone, and above it everything is air.
+pub const TERRAIN_BOTTOM: i32 = -64;
+pub const TERRAIN_TOP: i32 = 64;
+
+pub type Grid = [[[u32; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE];
+pub type LightGrid = [[[u16; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE];
+
+// A chunk with a one block border around it, see ChunkSnapshot.
+pub const PADDED_SIZE: usize = CHUNK_SIZE + 2;
+
+pub type PaddedGrid = [[[u32; PADDED_SIZE]; PADDED_SIZE]; PADDED_SIZE];
+pub type PaddedLightGrid = [[[u16; PADDED_SIZE]; PADDED_SIZE]; PADDED_SIZE];
+
 pub struct Chunk {
     pub position: cgmath::Vector3<i32>,
-    pub grid: Box<[[[u32; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE]>,
-    pub mesh: Option<Mesh>,
+    // Block state ids, see BlockList::states.
+    pub grid: Box<Grid>,
+    // Light levels of every block, 0..15, packed as four bits each of sky, red, green and blue, lowest first.
+    pub light: Box<LightGrid>,
+    // None if there's nothing to draw.
+    pub mesh: Option<ChunkMesh>,
+    pub translucent_mesh: Option<ChunkMesh>,
     
     pub should_regen_mesh: bool,
+    // The latest mesh job sent off for this chunk, older ones finishing late are thrown away. 0 if there's been none.
+    pub mesh_job: u64,
+    // Whether it's had a mesh made for it yet, even if that's empty.
+    pub meshed: bool,
+    // Which faces can be seen through the chunk from which, see ChunkSnapshot::connectivity.
+    // Everything's connected until it's been meshed.
+    pub connectivity: [u8; 6],
     //pub active_neighbors: u8,
 }
 
+// Everything needed to mesh a chunk, copied out of the world so meshing doesn't depend on it.
+// That way it can be done on any thread, or on blocks made up by hand.
+pub struct ChunkSnapshot {
+    pub position: Vector3<i32>,
+    // The chunk's blocks along with a one block border taken from the chunks around it,
+    // so the chunk's own blocks are at 1..16 on each axis. BlockTable::UNLOADED where a neighbor isn't loaded.
+    pub blocks: Box<PaddedGrid>,
+    // Light laid out the same way, full sky light where a neighbor isn't loaded.
+    pub light: Box<PaddedLightGrid>,
+    pub tints: [[Color; CHUNK_SIZE]; CHUNK_SIZE],
+    pub smooth_lighting: bool,
+    pub table: Arc<BlockTable>,
+}
+
+// The geometry of a chunk, split by how it has to be drawn.
+// It's all quads, four vertices each, drawn with the renderer's shared quad indices.
+pub struct MeshParts {
+    pub vertices: Vec<ChunkVertex>,
+    pub translucent_vertices: Vec<ChunkVertex>,
+}
+
+impl MeshParts {
+
+    pub fn new() -> MeshParts {
+        MeshParts {
+            vertices: Vec::new(),
+            translucent_vertices: Vec::new(),
+        }
+    }
+
+    // The vertices faces of a block rendered this way go into.
+    pub fn get_vertices(&mut self, render: RenderType) -> &mut Vec<ChunkVertex> {
+        if render == RenderType::Translucent {
+            &mut self.translucent_vertices
+        }
+        else {
+            &mut self.vertices
+        }
+    }
+
+    #[cfg(test)]
+    pub fn quad_count(&self) -> usize {
+        (self.vertices.len() + self.translucent_vertices.len()) / 4
+    }
+}
+
+// Everything about how a face looks, faces that share all of it can be merged.
+#[derive(Copy, Clone, PartialEq)]
+struct FaceKey {
+    state: u32,
+    color: [f32; 3],
+    light: [[f32; 4]; 4],
+    ao: [u8; 4],
+}
+
+// The 3x3x3 blocks around one being meshed, used for ambient occlusion and lighting.
+pub struct Neighborhood {
+    pub opaque: [[[bool; 3]; 3]; 3],
+    // Sky, red, green and blue light, 0..1.
+    pub light: [[[[f32; 4]; 3]; 3]; 3],
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum Face {
     Front,
     Back,
@@ -27,6 +118,23 @@ pub enum Face {
     Down,
 }
 
+impl Face {
+
+    pub const ALL: [Face; 6] = [Face::Left, Face::Right, Face::Down, Face::Up, Face::Front, Face::Back];
+
+    // The direction in the grid of the neighbor this face is up against.
+    pub fn direction(&self) -> Vector3<i32> {
+        match self {
+            Face::Front => Chunk::BACKWARD,
+            Face::Back => Chunk::FORWARD,
+            Face::Left => Chunk::LEFT,
+            Face::Right => Chunk::RIGHT,
+            Face::Up => Chunk::UP,
+            Face::Down => Chunk::DOWN,
+        }
+    }
+}
+
 impl Chunk {
 
     pub const FORWARD: Vector3<i32> = Vector3::new(0, 0, 1);
@@ -36,6 +144,9 @@ impl Chunk {
     pub const LEFT: Vector3<i32> = Vector3::new(-1, 0, 0);
     pub const RIGHT: Vector3<i32> = Vector3::new(1, 0, 0);
 
+    // A bit for every face, in the order of Face::ALL.
+    pub const ALL_FACES: u8 = 0b111111;
+
     pub fn get_world_position(&self) -> Vector3<f32> {
         Vector3::new(
             self.position.x as f32 + 0.5,
@@ -44,7 +155,7 @@ impl Chunk {
         ) * CHUNK_SIZE as f32
     }
 
-    pub fn get_noise_value_at(position: cgmath::Vector3<i32>, world: &World) -> f32 {
+    pub fn get_noise_value_at(position: cgmath::Vector3<i32>, noise: &FastNoise) -> f32 {
 
         let max_height: f32 = 64.0;
         let noise_scale = 0.03;
@@ -52,12 +163,23 @@ impl Chunk {
         let ny = position.y as f32 * noise_scale;
         let nz = position.z as f32 * noise_scale;
 
-        let mut value = world.noise.get_noise3d(nx, ny, nz);
+        let mut value = noise.get_noise3d(nx, ny, nz);
         value += 1.0 - ((position.y as f32 + 64.0) / max_height);
         value
     }
 
-    pub fn new(position: cgmath::Vector3<i32>, world: &World) -> Chunk {
+    pub fn new(position: cgmath::Vector3<i32>, noise: &FastNoise, block_list: &BlockList) -> Chunk {
+
+        let grid = Chunk::generate_grid(position, 1, noise, block_list);
+        let light = Box::new([[[0; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE]);
+
+        Chunk { grid, light, position, mesh: None, translucent_mesh: None, should_regen_mesh: true, mesh_job: 0, meshed: false, connectivity: [Chunk::ALL_FACES; 6] } //active_neighbors: 0, should_regen_mesh: false }
+
+    }
+
+    // Generates the blocks of a chunk, or with a 'scale' above 1, of a region that many chunks across on each axis,
+    // one block sampled out of every 'scale' on each axis. Those are what far off terrain is drawn with.
+    pub fn generate_grid(position: cgmath::Vector3<i32>, scale: i32, noise: &FastNoise, block_list: &BlockList) -> Box<Grid> {
 
         let mut grid = Box::new([[[0; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE]);
 
@@ -65,23 +187,23 @@ impl Chunk {
             for y in (0..CHUNK_SIZE).rev() {
                 for x in 0..CHUNK_SIZE {
                     
-                    let block_pos = (position * CHUNK_SIZE as i32) + cgmath::Vector3::new(x as i32, y as i32, z as i32);
+                    let block_pos = ((position * CHUNK_SIZE as i32) + cgmath::Vector3::new(x as i32, y as i32, z as i32)) * scale;
                     
                     let value: f32;
-                    if block_pos.y < -64 {
+                    if block_pos.y < TERRAIN_BOTTOM {
                         value = 1.0;
                     }
-                    else if block_pos.y > 64 {
+                    else if block_pos.y > TERRAIN_TOP {
                         value = 0.0;
                     }
                     else {
-                        value = Chunk::get_noise_value_at(block_pos, world);
+                        value = Chunk::get_noise_value_at(block_pos, noise);
                     }
             
                     let mut covered = false;
 
                     if y == GRID_MAX {
-                        let block_above = Chunk::get_noise_value_at(block_pos + Vector3::new(0, 1, 0), world);
+                        let block_above = Chunk::get_noise_value_at(block_pos + Vector3::new(0, scale, 0), noise);
                         if block_above > 0.0 { covered = true; }
                     }
                     else if grid[x][y+1][z] != 0 {
@@ -90,9 +212,9 @@ impl Chunk {
 
                     if value > 0.0 {
 
-                        let stone = world.block_list.get_block("stone").unwrap().id;
-                        let dirt = world.block_list.get_block("dirt").unwrap().id;
-                        let grass = world.block_list.get_block("grass").unwrap().id;
+                        let stone = block_list.get_block("stone").unwrap().default_state;
+                        let dirt = block_list.get_block("dirt").unwrap().default_state;
+                        let grass = block_list.get_block("grass").unwrap().default_state;
 
                         if value > 0.1 || covered {
                             if value > 0.15 {
@@ -124,34 +246,137 @@ impl Chunk {
             }
         }
 
-        Chunk { grid, position, mesh: None, should_regen_mesh: true } //active_neighbors: 0, should_regen_mesh: false }
+        grid
+    }
+
+    // Splits a block position into the position of the chunk it's in and its position within that chunk.
+    pub fn locate(position: Vector3<i32>) -> (Vector3<i32>, Vector3<usize>) {
+        let cs = CHUNK_SIZE as i32;
+        (
+            Vector3::new(position.x.div_euclid(cs), position.y.div_euclid(cs), position.z.div_euclid(cs)),
+            Vector3::new(position.x.rem_euclid(cs) as usize, position.y.rem_euclid(cs) as usize, position.z.rem_euclid(cs) as usize),
+        )
+    }
+
+    pub fn get_sky_light(&self, x: usize, y: usize, z: usize) -> u8 {
+        (self.light[x][y][z] & 0xF) as u8
+    }
+
+    pub fn set_sky_light(&mut self, x: usize, y: usize, z: usize, level: u8) {
+        self.light[x][y][z] = (self.light[x][y][z] & !0xF) | level as u16;
+    }
+
+    // Returns the red, green and blue block light levels.
+    pub fn get_block_light(&self, x: usize, y: usize, z: usize) -> [u8; 3] {
+        Chunk::unpack_block_light(self.light[x][y][z])
+    }
 
+    pub fn unpack_block_light(light: u16) -> [u8; 3] {
+        [((light >> 4) & 0xF) as u8, ((light >> 8) & 0xF) as u8, ((light >> 12) & 0xF) as u8]
+    }
+
+    // Sets one channel of block light, 0..3 being red, green and blue.
+    pub fn set_block_light(&mut self, x: usize, y: usize, z: usize, channel: usize, level: u8) {
+        let shift = 4 * (channel + 1);
+        self.light[x][y][z] = (self.light[x][y][z] & !(0xF << shift)) | ((level as u16) << shift);
     }
 
     pub fn center(&self) -> Vector3<f32> {
         Vector3::new(self.position.x as f32 + 0.5, self.position.y as f32 + 0.5, self.position.z as f32 + 0.5)
     }
 
-    // Assumed ordered CCW.
-    pub fn triangulate_quad(p1: Vector3<f32>, p2: Vector3<f32>, p3: Vector3<f32>, p4: Vector3<f32>, vertices: &mut Vec<Vertex>, indices: &mut Vec<u16>, normal: Vector3<f32>, tex_coords: ([f32; 2], [f32; 2], [f32; 2], [f32; 2])) {
-        
-        vertices.push(Vertex { position: p1.into(), normal: normal.into(), tex_coords: tex_coords.0 });
-        vertices.push(Vertex { position: p2.into(), normal: normal.into(), tex_coords: tex_coords.1 });
-        vertices.push(Vertex { position: p3.into(), normal: normal.into(), tex_coords: tex_coords.2 });
-        vertices.push(Vertex { position: p4.into(), normal: normal.into(), tex_coords: tex_coords.3 });
+    // Assumed ordered CCW, within the chunk.
+    // Quads are always split along their first and third corners, so to split one along the less occluded
+    // diagonal instead, its corners are started one further along. That way occlusion looks the same
+    // no matter which way the quad is facing.
+    pub fn triangulate_quad(points: [Vector3<f32>; 4], vertices: &mut Vec<ChunkVertex>, normal: Vector3<f32>, tex_coords: [[f32; 2]; 4], tile: u32, color: [f32; 3], light: [[f32; 4]; 4], ao: [u8; 4]) {
+
+        let axis = if normal.x != 0.0 { 0 } else if normal.y != 0.0 { 1 } else { 2 };
+        let normal_index = axis as u32 * 2 + (normal[axis] > 0.0) as u32;
+        let orientation = Chunk::texture_orientation(&points, &tex_coords, axis);
+
+        let first = if ao[0] + ao[2] >= ao[1] + ao[3] { 0 } else { 1 };
+
+        for i in (0..4).map(|i| (i + first) % 4) {
+            let p = points[i];
+            let position = [p.x.round() as i32, p.y.round() as i32, p.z.round() as i32];
+            vertices.push(ChunkVertex::new(position, normal_index, orientation, ao[i], tile, light[i], color));
+        }
+    }
+
+    // How texture coordinates follow a face, which the shaders rebuild them from rather than storing them.
+    // Bit 0 is set when u runs along the face's second axis instead of its first, see ChunkVertex,
+    // and bits 1 and 2 when u and v run the opposite way to their axes.
+    pub fn texture_orientation(points: &[Vector3<f32>; 4], tex_coords: &[[f32; 2]; 4], axis: usize) -> u32 {
+
+        let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+        let edge = |i: usize, j: usize| (points[j] - points[i], [tex_coords[j][0] - tex_coords[i][0], tex_coords[j][1] - tex_coords[i][1]]);
+
+        // The first two edges run along different axes, so one of them runs along each.
+        let (first, second) = (edge(0, 1), edge(1, 2));
+        let (along_a, along_b) = if first.0[a] != 0.0 { (first, second) } else { (second, first) };
+
+        let swapped = along_a.1[0] == 0.0;
+        let (u_rate, v_rate) = if swapped {
+            (along_b.1[0] / along_b.0[b], along_a.1[1] / along_a.0[a])
+        }
+        else {
+            (along_a.1[0] / along_a.0[a], along_b.1[1] / along_b.0[b])
+        };
+
+        swapped as u32 | ((u_rate < 0.0) as u32) << 1 | ((v_rate < 0.0) as u32) << 2
+    }
+
+    // The two axes running along a face, pointing towards the given corner.
+    fn corner_axes(normal: Vector3<i32>, corner: Vector3<i32>) -> (Vector3<i32>, Vector3<i32>) {
+        if normal.x != 0 { (Vector3::new(0, corner.y, 0), Vector3::new(0, 0, corner.z)) }
+        else if normal.y != 0 { (Vector3::new(corner.x, 0, 0), Vector3::new(0, 0, corner.z)) }
+        else { (Vector3::new(corner.x, 0, 0), Vector3::new(0, corner.y, 0)) }
+    }
+
+    // Ambient occlusion of a face's corner, from 0 for fully occluded to 3 for not occluded at all.
+    // Looks at the two blocks beside the corner and the one diagonal to it, on the face's open side.
+    // 'corner' is the direction of the corner on each axis.
+    pub fn vertex_ao(neighborhood: &Neighborhood, normal: Vector3<i32>, corner: Vector3<i32>) -> u8 {
+
+        let (u, v) = Chunk::corner_axes(normal, corner);
+        let occluded = |p: Vector3<i32>| neighborhood.opaque[(p.x + 1) as usize][(p.y + 1) as usize][(p.z + 1) as usize] as u8;
+
+        let side_1 = occluded(normal + u);
+        let side_2 = occluded(normal + v);
+        let corner = occluded(normal + u + v);
+
+        if side_1 == 1 && side_2 == 1 { 0 } else { 3 - side_1 - side_2 - corner }
+    }
+
+    // Light of a face's corner, averaged over the four blocks touching it on the face's open side.
+    // Opaque blocks hold no light, so they're left out rather than darkening the corner.
+    // If all four are opaque, the corner gets the face's flat light instead.
+    pub fn vertex_light(neighborhood: &Neighborhood, normal: Vector3<i32>, corner: Vector3<i32>) -> [f32; 4] {
 
-        let l = vertices.len() as u16;
-        indices.push(l - 4);
-        indices.push(l - 3);
-        indices.push(l - 2);
+        let (u, v) = Chunk::corner_axes(normal, corner);
+        let mut total = [0.0; 4];
+        let mut count = 0.0;
+
+        for p in [normal, normal + u, normal + v, normal + u + v].iter() {
+            let (x, y, z) = ((p.x + 1) as usize, (p.y + 1) as usize, (p.z + 1) as usize);
+            if neighborhood.opaque[x][y][z] { continue; }
+
+            for (sum, level) in total.iter_mut().zip(neighborhood.light[x][y][z].iter()) {
+                *sum += level;
+            }
+            count += 1.0;
+        }
 
-        indices.push(l - 4);
-        indices.push(l - 2);
-        indices.push(l - 1);
+        if count == 0.0 {
+            return neighborhood.light[(normal.x + 1) as usize][(normal.y + 1) as usize][(normal.z + 1) as usize];
+        }
 
+        [total[0] / count, total[1] / count, total[2] / count, total[3] / count]
     }
 
-    pub fn build_face(origin: Vector3<f32>, face: Face, vertices: &mut Vec<Vertex>, indices: &mut Vec<u16>, block: &Block, atlas: &image::DynamicImage) {
+    // The corners of a block's face, ordered CCW, with the face's normal and the side of the block it shows.
+    pub fn face_corners(origin: Vector3<f32>, face: Face) -> ([Vector3<f32>; 4], Vector3<f32>, block::Side) {
 
         let a = origin;
         let b = a + Block::RIGHT;
@@ -164,160 +389,533 @@ impl Chunk {
         let h = g + Block::LEFT;
 
         match face {
+            Face::Front => ([h, g, c, d], Block::FORWARD, block::Side::Front),
+            Face::Back => ([f, e, a, b], Block::BACKWARD, block::Side::Back),
+            Face::Left => ([e, h, d, a], Block::LEFT, block::Side::Left),
+            Face::Right => ([g, f, b, c], Block::RIGHT, block::Side::Right),
+            Face::Up => ([g, h, e, f], Block::UP, block::Side::Top),
+            Face::Down => ([c, b, a, d], Block::DOWN, block::Side::Bottom),
+        }
+    }
 
-            Face::Front => {
-                Chunk::triangulate_quad(h, g, c, d, vertices, indices, Block::FORWARD, block.get_tex_coords(block::Side::Front, atlas));
-            }
+    // Which corner of a block a point on it is, as a direction in the grid.
+    // Blocks span -1 on the z axis from their origin, towards the neighbor at z - 1 in the grid.
+    fn corner_direction(offset: Vector3<f32>) -> Vector3<i32> {
+        Vector3::new(
+            if offset.x > 0.5 { 1 } else { -1 },
+            if offset.y > 0.5 { 1 } else { -1 },
+            if offset.z < -0.5 { -1 } else { 1 },
+        )
+    }
 
-            Face::Back => {
-                Chunk::triangulate_quad(f, e, a, b, vertices, indices, Block::BACKWARD, block.get_tex_coords(block::Side::Back, atlas));
-            }
+    // Light and ambient occlusion of each corner of a face.
+    // Faces are lit by the block they face out into, either as a whole or smoothly per corner.
+    pub fn face_shading(origin: Vector3<f32>, face: Face, corners: &[Vector3<f32>; 4], neighborhood: &Neighborhood, smooth_lighting: bool) -> ([[f32; 4]; 4], [u8; 4]) {
 
-            Face::Left => {
-                Chunk::triangulate_quad(e, h, d, a, vertices, indices, Block::LEFT, block.get_tex_coords(block::Side::Left, atlas));
-            }
+        let normal = face.direction();
+        let flat_light = neighborhood.light[(normal.x + 1) as usize][(normal.y + 1) as usize][(normal.z + 1) as usize];
 
-            Face::Right => {
-                Chunk::triangulate_quad(g, f, b, c, vertices, indices, Block::RIGHT, block.get_tex_coords(block::Side::Right, atlas));
+        let mut light = [flat_light; 4];
+        let mut ao = [3; 4];
+        for (i, p) in corners.iter().enumerate() {
+            let corner = Chunk::corner_direction(p - origin);
+            if smooth_lighting {
+                light[i] = Chunk::vertex_light(neighborhood, normal, corner);
             }
+            ao[i] = Chunk::vertex_ao(neighborhood, normal, corner);
+        }
+        (light, ao)
+    }
 
-            Face::Up => {
-                Chunk::triangulate_quad(g, h, e, f, vertices, indices, Block::UP, block.get_tex_coords(block::Side::Top, atlas));
-            }
+    pub fn face_color(texture: &SideTexture, tint: Color) -> [f32; 3] {
+        if texture.tinted { tint.into() } else { [1.0, 1.0, 1.0] }
+    }
+
+    #[cfg(test)]
+    pub fn build_face(origin: Vector3<f32>, face: Face, vertices: &mut Vec<ChunkVertex>, properties: &block::StateProperties, tint: Color, neighborhood: &Neighborhood, smooth_lighting: bool) {
 
-            Face::Down => {
-                Chunk::triangulate_quad(c, b, a, d, vertices, indices, Block::DOWN, block.get_tex_coords(block::Side::Bottom, atlas));
+        let (corners, normal, side) = Chunk::face_corners(origin, face);
+        let (light, ao) = Chunk::face_shading(origin, face, &corners, neighborhood, smooth_lighting);
+        let texture = &properties.sides[side.index()];
+        let color = Chunk::face_color(texture, tint);
+
+        Chunk::triangulate_quad(corners, vertices, normal, texture.tex_coords, texture.tile, color, light, ao);
+    }
+}
+
+impl ChunkSnapshot {
+
+    // Light where a neighbor isn't loaded, as if it were open sky.
+    pub const UNLOADED_LIGHT: u16 = MAX_LIGHT as u16;
+
+    // A snapshot with nothing loaded, the chunk included, to be filled in with copy_chunk.
+    pub fn new(position: Vector3<i32>, tints: [[Color; CHUNK_SIZE]; CHUNK_SIZE], smooth_lighting: bool, table: Arc<BlockTable>) -> ChunkSnapshot {
+        ChunkSnapshot {
+            position,
+            blocks: Box::new([[[BlockTable::UNLOADED; PADDED_SIZE]; PADDED_SIZE]; PADDED_SIZE]),
+            light: Box::new([[[ChunkSnapshot::UNLOADED_LIGHT; PADDED_SIZE]; PADDED_SIZE]; PADDED_SIZE]),
+            tints,
+            smooth_lighting,
+            table,
+        }
+    }
+
+    // A snapshot of a far off region, see Chunk::generate_grid. It's meshed on its own, so every face on its edges is kept,
+    // hanging down or up past the surface around it and hiding any gaps where it meets more detailed terrain.
+    // Light is left out, so everything's in full daylight.
+    pub fn lod(position: Vector3<i32>, grid: &Grid, tints: [[Color; CHUNK_SIZE]; CHUNK_SIZE], table: Arc<BlockTable>) -> ChunkSnapshot {
+
+        let mut snapshot = ChunkSnapshot::new(position, tints, false, table);
+        snapshot.copy_chunk(Vector3::new(0, 0, 0), grid, &[[[ChunkSnapshot::UNLOADED_LIGHT; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE]);
+        snapshot
+    }
+
+    // Copies in the blocks and light of the chunk, or of a neighbor by its offset from the chunk on each axis, -1..1.
+    // Only the part of a neighbor touching the chunk is kept.
+    pub fn copy_chunk(&mut self, offset: Vector3<i32>, grid: &Grid, light: &LightGrid) {
+
+        // The padded positions along an axis that get filled, and where in the grid the first of them comes from.
+        let range = |offset: i32| match offset {
+            -1 => (0..1, GRID_MAX),
+            0 => (1..CHUNK_SIZE + 1, 0),
+            _ => (CHUNK_SIZE + 1..PADDED_SIZE, 0),
+        };
+        let ((xs, gx), (ys, gy), (zs, gz)) = (range(offset.x), range(offset.y), range(offset.z));
+
+        for x in xs.clone() {
+            for y in ys.clone() {
+                for z in zs.clone() {
+                    let (lx, ly, lz) = (x - xs.start + gx, y - ys.start + gy, z - zs.start + gz);
+                    self.blocks[x][y][z] = grid[lx][ly][lz];
+                    self.light[x][y][z] = light[lx][ly][lz];
+                }
             }
+        }
+    }
+
+    // Which faces of the chunk are connected to which through blocks that aren't opaque,
+    // a bit per face for every face, both in the order of Face::ALL.
+    // Found by flood filling every pocket of air in the chunk and noting which faces it touches.
+    pub fn connectivity(&self) -> [u8; 6] {
+
+        let index = |x: usize, y: usize, z: usize| (x * CHUNK_SIZE + y) * CHUNK_SIZE + z;
+        let opaque = |x: usize, y: usize, z: usize| self.table.is_opaque(self.blocks[x + 1][y + 1][z + 1]);
+
+        let mut connectivity = [0; 6];
+        let mut filled = vec![false; CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE];
+        let mut stack = Vec::new();
+
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+
+                    if filled[index(x, y, z)] || opaque(x, y, z) { continue; }
 
+                    filled[index(x, y, z)] = true;
+                    stack.push(Vector3::new(x as i32, y as i32, z as i32));
+                    let mut faces = 0;
+
+                    while let Some(p) = stack.pop() {
+                        for (i, face) in Face::ALL.iter().enumerate() {
+                            let n = p + face.direction();
+                            if n.x < 0 || n.y < 0 || n.z < 0 || n.x > GRID_MAX as i32 || n.y > GRID_MAX as i32 || n.z > GRID_MAX as i32 {
+                                faces |= 1 << i;
+                                continue;
+                            }
+
+                            let (nx, ny, nz) = (n.x as usize, n.y as usize, n.z as usize);
+                            if filled[index(nx, ny, nz)] || opaque(nx, ny, nz) { continue; }
+                            filled[index(nx, ny, nz)] = true;
+                            stack.push(n);
+                        }
+                    }
+
+                    for (i, connected) in connectivity.iter_mut().enumerate() {
+                        if faces & (1 << i) != 0 {
+                            *connected |= faces;
+                        }
+                    }
+                }
+            }
         }
+
+        connectivity
+    }
+
+    // Returns the state at a position relative to the chunk, -1..16 on each axis so reaching one block into its neighbors.
+    pub fn get_state_at(&self, x: i32, y: i32, z: i32) -> u32 {
+        self.blocks[(x + 1) as usize][(y + 1) as usize][(z + 1) as usize]
+    }
+
+    // Returns the sky and block light at a position relative to the chunk, see get_state_at.
+    pub fn get_light_at(&self, x: i32, y: i32, z: i32) -> (u8, [u8; 3]) {
+        let light = self.light[(x + 1) as usize][(y + 1) as usize][(z + 1) as usize];
+        ((light & 0xF) as u8, Chunk::unpack_block_light(light))
     }
 
-    // Returns the vertices and indices of a chunk mesh.
-    pub fn generate_mesh_parts(&self, world: &World) -> (Vec<Vertex>, Vec<u16>) {
-        
-        let mut vertices: Vec<Vertex> = Vec::new();
-        let mut indices: Vec<u16> = Vec::new();
+    // Calls 'f' with every visible face of every block in the chunk, along with the blocks around it.
+    fn for_each_visible_face<F: FnMut(usize, usize, usize, u32, Face, &Neighborhood)>(&self, mut f: F) {
 
         for z in 0..CHUNK_SIZE {
             for y in 0..CHUNK_SIZE {
                 for x in 0..CHUNK_SIZE {
 
-                    let block_id = self.grid[x][y][z];
-                    if block_id != 0 {
-            
-                        let offset: Vector3<f32> = Vector3::new(x as f32, y as f32, z as f32);
-                        let chunk_position: Vector3<f32> = Vector3::new(self.position.x as f32, self.position.y as f32, self.position.z as f32) * CHUNK_SIZE as f32;
-            
-                        let origin = chunk_position + offset;
-                        let atlas = &world.block_atlas;
-
-                        let block = world.block_list.blocks.get(block_id as usize).unwrap();
-                        
-                        if x == 0 {
-                            match world.chunks.get(&(self.position + Chunk::LEFT)) {
-                                Some(chunk) => {
-                                    if chunk.grid[GRID_MAX][y][z] == 0 {
-                                        Chunk::build_face(origin, Face::Left, &mut vertices, &mut indices, block, atlas);
-                                    }
-                                }
-                                None => {
-                                    Chunk::build_face(origin, Face::Left, &mut vertices, &mut indices, block, atlas);
-                                }
+                    let state_id = self.get_state_at(x as i32, y as i32, z as i32);
+                    if state_id == 0 { continue; }
+
+                    // Faces against unloaded chunks are kept, they get culled once the neighbor loads.
+                    let visible: Vec<Face> = Face::ALL.iter().cloned().filter(|face| {
+                        let dir = face.direction();
+                        let neighbor = self.get_state_at(x as i32 + dir.x, y as i32 + dir.y, z as i32 + dir.z);
+                        self.table.is_face_visible(state_id, neighbor)
+                    }).collect();
+
+                    if visible.is_empty() { continue; }
+
+                    let mut neighborhood = Neighborhood {
+                        opaque: [[[false; 3]; 3]; 3],
+                        light: [[[[0.0; 4]; 3]; 3]; 3],
+                    };
+
+                    for dx in 0..3 {
+                        for dy in 0..3 {
+                            for dz in 0..3 {
+                                let (nx, ny, nz) = (x as i32 + dx as i32 - 1, y as i32 + dy as i32 - 1, z as i32 + dz as i32 - 1);
+
+                                neighborhood.opaque[dx][dy][dz] = self.table.is_opaque(self.get_state_at(nx, ny, nz));
+
+                                let (sky_light, block_light) = self.get_light_at(nx, ny, nz);
+                                neighborhood.light[dx][dy][dz] = [
+                                    sky_light as f32 / MAX_LIGHT as f32,
+                                    block_light[0] as f32 / MAX_LIGHT as f32,
+                                    block_light[1] as f32 / MAX_LIGHT as f32,
+                                    block_light[2] as f32 / MAX_LIGHT as f32,
+                                ];
                             }
                         }
-                        else if self.grid[x-1][y][z] == 0 {
-                            Chunk::build_face(origin, Face::Left, &mut vertices, &mut indices, block, atlas);
+                    }
+
+                    for face in visible {
+                        f(x, y, z, state_id, face, &neighborhood);
+                    }
+                }
+            }
+        }
+    }
+
+    // One quad per visible face. Kept around for the tests to compare greedy meshing against.
+    #[cfg(test)]
+    pub fn generate_naive_mesh_parts(&self) -> MeshParts {
+
+        let mut parts = MeshParts::new();
+
+        self.for_each_visible_face(|x, y, z, state_id, face, neighborhood| {
+
+            let origin = Vector3::new(x as f32, y as f32, z as f32);
+            let properties = self.table.get(state_id);
+
+            let vertices = parts.get_vertices(properties.render);
+            Chunk::build_face(origin, face, vertices, properties, self.tints[x][z], neighborhood, self.smooth_lighting);
+        });
+
+        parts
+    }
+
+    // Returns the vertices of a chunk mesh, translucent faces being kept apart
+    // so they can be drawn after everything else.
+    // Faces are merged into larger quads wherever neighboring faces on the same plane look exactly alike,
+    // the same state, tint, light and occlusion, one layer of the chunk at a time.
+    // Everything is positioned within the chunk, it's moved into place when drawn.
+    pub fn generate_mesh_parts(&self) -> MeshParts {
+
+        let mut parts = MeshParts::new();
+
+        let index = |face: usize, p: [usize; 3]| ((face * CHUNK_SIZE + p[0]) * CHUNK_SIZE + p[1]) * CHUNK_SIZE + p[2];
+        let mut faces: Vec<Option<FaceKey>> = vec![None; 6 * CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE];
+
+        self.for_each_visible_face(|x, y, z, state_id, face, neighborhood| {
+
+            let origin = Vector3::new(x as f32, y as f32, z as f32);
+            let properties = self.table.get(state_id);
+
+            let (corners, _, side) = Chunk::face_corners(origin, face);
+            let (light, ao) = Chunk::face_shading(origin, face, &corners, neighborhood, self.smooth_lighting);
+            let color = Chunk::face_color(&properties.sides[side.index()], self.tints[x][z]);
+
+            let face_index = Face::ALL.iter().position(|f| *f == face).unwrap();
+            faces[index(face_index, [x, y, z])] = Some(FaceKey { state: state_id, color, light, ao });
+        });
+
+        for (face_index, face) in Face::ALL.iter().enumerate() {
+
+            // The axis the face points along, and the two the face lies on.
+            let n = if face.direction().x != 0 { 0 } else if face.direction().y != 0 { 1 } else { 2 };
+            let (u, v) = ((n + 1) % 3, (n + 2) % 3);
+
+            let at = |layer: usize, a: usize, b: usize| {
+                let mut p = [0; 3];
+                p[n] = layer;
+                p[u] = a;
+                p[v] = b;
+                p
+            };
+
+            for layer in 0..CHUNK_SIZE {
+
+                let mut done = [[false; CHUNK_SIZE]; CHUNK_SIZE];
+                let key_at = |a: usize, b: usize| faces[index(face_index, at(layer, a, b))];
+
+                for b in 0..CHUNK_SIZE {
+                    for a in 0..CHUNK_SIZE {
+
+                        if done[a][b] { continue; }
+                        let key = match key_at(a, b) {
+                            Some(key) => key,
+                            None => continue,
+                        };
+
+                        // Grows along the first axis, then along the second for as long as whole rows match.
+                        let mut width = 1;
+                        while a + width < CHUNK_SIZE && !done[a + width][b] && key_at(a + width, b) == Some(key) {
+                            width += 1;
                         }
 
-                        if x == GRID_MAX {
-                            match world.chunks.get(&(self.position + Chunk::RIGHT)) {
-                                Some(chunk) => {
-                                    if chunk.grid[0][y][z] == 0 {
-                                        Chunk::build_face(origin, Face::Right, &mut vertices, &mut indices, block, atlas);
-                                    }
-                                }
-                                None => {
-                                    Chunk::build_face(origin, Face::Right, &mut vertices, &mut indices, block, atlas);
+                        let mut height = 1;
+                        'grow: while b + height < CHUNK_SIZE {
+                            for i in 0..width {
+                                if done[a + i][b + height] || key_at(a + i, b + height) != Some(key) {
+                                    break 'grow;
                                 }
                             }
+                            height += 1;
                         }
-                        else if self.grid[x+1][y][z] == 0 {
-                            Chunk::build_face(origin, Face::Right, &mut vertices, &mut indices, block, atlas);
-                        }
-            
-                        if y == 0 {
-                            match world.chunks.get(&(self.position + Chunk::DOWN)) {
-                                Some(chunk) => {
-                                    if chunk.grid[x][GRID_MAX][z] == 0 {
-                                        Chunk::build_face(origin, Face::Down, &mut vertices, &mut indices, block, atlas);
-                                    }
-                                }
-                                None => {
-                                    Chunk::build_face(origin, Face::Down, &mut vertices, &mut indices, block, atlas);
-                                }
+
+                        for row in done.iter_mut().skip(a).take(width) {
+                            for cell in row.iter_mut().skip(b).take(height) {
+                                *cell = true;
                             }
                         }
-                        else if self.grid[x][y-1][z] == 0 {
-                            Chunk::build_face(origin, Face::Down, &mut vertices, &mut indices, block, atlas);
-                        }
-            
-                        if y == GRID_MAX {
-                            match world.chunks.get(&(self.position + Chunk::UP)) {
-                                Some(chunk) => {
-                                    if chunk.grid[x][0][z] == 0 {
-                                        Chunk::build_face(origin, Face::Up, &mut vertices, &mut indices, block, atlas);
-                                    }
-                                }
-                                None => {
-                                    Chunk::build_face(origin, Face::Up, &mut vertices, &mut indices, block, atlas);
-                                }
-                            }
+
+                        let properties = self.table.get(key.state);
+                        let vertices = parts.get_vertices(properties.render);
+
+                        let block_origin = |a: usize, b: usize| {
+                            let p = at(layer, a, b);
+                            Vector3::new(p[0] as f32, p[1] as f32, p[2] as f32)
+                        };
+
+                        // Each corner of the merged quad is that same corner of the block at that end of it.
+                        let origin = block_origin(a, b);
+                        let (unit_corners, normal, side) = Chunk::face_corners(origin, *face);
+                        let directions: Vec<Vector3<i32>> = unit_corners.iter().map(|p| Chunk::corner_direction(p - origin)).collect();
+
+                        let mut corners = unit_corners;
+                        for (i, corner) in corners.iter_mut().enumerate() {
+                            let end_a = if directions[i][u] > 0 { a + width - 1 } else { a };
+                            let end_b = if directions[i][v] > 0 { b + height - 1 } else { b };
+                            *corner = Chunk::face_corners(block_origin(end_a, end_b), *face).0[i];
                         }
-                        else if self.grid[x][y+1][z] == 0 {
-                            Chunk::build_face(origin, Face::Up, &mut vertices, &mut indices, block, atlas);
+
+                        // The texture repeats once per block, along whichever edges it runs along.
+                        let SideTexture { tex_coords: unit_coords, tile, .. } = properties.sides[side.index()];
+                        let (first_edge, second_edge) = if directions[0][u] != directions[1][u] { (width, height) } else { (height, width) };
+                        let scale_u = if unit_coords[0][0] != unit_coords[1][0] { first_edge } else { second_edge } as f32;
+                        let scale_v = if unit_coords[0][1] != unit_coords[1][1] { first_edge } else { second_edge } as f32;
+
+                        let mut tex_coords = unit_coords;
+                        for coords in tex_coords.iter_mut() {
+                            *coords = [coords[0] * scale_u, coords[1] * scale_v];
                         }
 
-                        if z == 0 {
-                            match world.chunks.get(&(self.position + Chunk::BACKWARD)) {
-                                Some(chunk) => {
-                                    if chunk.grid[x][y][GRID_MAX] == 0 {
-                                        Chunk::build_face(origin, Face::Front, &mut vertices, &mut indices, block, atlas);
-                                    }
-                                }
-                                None => {
-                                    Chunk::build_face(origin, Face::Front, &mut vertices, &mut indices, block, atlas);
-                                }
-                            }
+                        Chunk::triangulate_quad(corners, vertices, normal, tex_coords, tile, key.color, key.light, key.ao);
+                    }
+                }
+            }
+        }
+
+        parts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bracket_noise::prelude::{FractalType, NoiseType};
+
+    use super::*;
+    use block::StateProperties;
+
+    const AIR: u32 = 0;
+    const STONE: u32 = 1;
+    const WATER: u32 = 2;
+
+    // Air, an opaque block and a translucent one, without needing the block list or atlas.
+    fn table() -> Arc<BlockTable> {
+        let sides = [SideTexture { tex_coords: [[0.0; 2]; 4], tile: 0, tinted: false }; 6];
+        let state = |block: u32, render: RenderType| StateProperties { block, render, opaque: render == RenderType::Opaque && block != 0, sides };
+        Arc::new(BlockTable { states: vec![state(0, RenderType::Opaque), state(1, RenderType::Opaque), state(2, RenderType::Translucent)] })
+    }
+
+    // A snapshot of a chunk of air, with its neighbors loaded and empty too if 'neighbors' is set.
+    fn snapshot(neighbors: bool) -> ChunkSnapshot {
+        let mut snapshot = ChunkSnapshot::new(Vector3::new(0, 0, 0), [[Color::from_rgb(1.0, 1.0, 1.0); CHUNK_SIZE]; CHUNK_SIZE], true, table());
+        let (grid, light) = ([[[AIR; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE], [[[0; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE]);
+        snapshot.copy_chunk(Vector3::new(0, 0, 0), &grid, &light);
+
+        if neighbors {
+            for x in -1..=1 {
+                for y in -1..=1 {
+                    for z in -1..=1 {
+                        if (x, y, z) != (0, 0, 0) {
+                            snapshot.copy_chunk(Vector3::new(x, y, z), &grid, &light);
                         }
-                        else if self.grid[x][y][z-1] == 0 {
-                            Chunk::build_face(origin, Face::Front, &mut vertices, &mut indices, block, atlas);
+                    }
+                }
+            }
+        }
+        snapshot
+    }
+
+    fn set(snapshot: &mut ChunkSnapshot, x: i32, y: i32, z: i32, state: u32) {
+        snapshot.blocks[(x + 1) as usize][(y + 1) as usize][(z + 1) as usize] = state;
+    }
+
+    #[test]
+    fn isolated_block_has_six_faces() {
+        let mut snapshot = snapshot(true);
+        set(&mut snapshot, 5, 5, 5, STONE);
+
+        for parts in [snapshot.generate_naive_mesh_parts(), snapshot.generate_mesh_parts()].iter() {
+            assert_eq!(parts.quad_count(), 6);
+            assert!(parts.translucent_vertices.is_empty());
+        }
+    }
+
+    #[test]
+    fn translucent_blocks_hide_faces_against_each_other() {
+        let mut snapshot = snapshot(true);
+        set(&mut snapshot, 5, 5, 5, WATER);
+        set(&mut snapshot, 6, 5, 5, WATER);
+
+        // Two blocks' worth of faces less the two they share.
+        let parts = snapshot.generate_naive_mesh_parts();
+        assert_eq!(parts.quad_count(), 10);
+        assert!(parts.vertices.is_empty());
+
+        // Stone against the water hides the water's face, but not its own.
+        set(&mut snapshot, 7, 5, 5, STONE);
+        let parts = snapshot.generate_naive_mesh_parts();
+        assert_eq!(parts.translucent_vertices.len() / 4, 9);
+        assert_eq!(parts.vertices.len() / 4, 6);
+    }
+
+    #[test]
+    fn faces_against_unloaded_neighbors_are_kept() {
+        // In the corner of a chunk with no neighbors loaded, three faces are against unloaded blocks.
+        let mut snapshot = snapshot(false);
+        set(&mut snapshot, 0, 0, 0, STONE);
+        assert_eq!(snapshot.get_state_at(-1, 0, 0), BlockTable::UNLOADED);
+        assert_eq!(snapshot.generate_naive_mesh_parts().quad_count(), 6);
+
+        // A loaded neighbor hides the face against it.
+        set(&mut snapshot, -1, 0, 0, STONE);
+        assert_eq!(snapshot.generate_naive_mesh_parts().quad_count(), 5);
+    }
+
+    #[test]
+    fn greedy_meshing_emits_fewer_vertices() {
+
+        // The same noise the world generates terrain with, on a fixed seed.
+        let mut noise = FastNoise::seeded(1);
+        noise.set_noise_type(NoiseType::SimplexFractal);
+        noise.set_fractal_type(FractalType::FBM);
+        noise.set_fractal_octaves(2);
+        noise.set_fractal_gain(0.1);
+        noise.set_fractal_lacunarity(2.0);
+        noise.set_frequency(0.3);
+
+        let terrain = |position: Vector3<i32>| {
+            let mut grid = [[[AIR; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE];
+            for (x, plane) in grid.iter_mut().enumerate() {
+                for (y, column) in plane.iter_mut().enumerate() {
+                    for (z, state) in column.iter_mut().enumerate() {
+                        let block_pos = position * CHUNK_SIZE as i32 + Vector3::new(x as i32, y as i32, z as i32);
+                        if Chunk::get_noise_value_at(block_pos, &noise) > 0.0 {
+                            *state = STONE;
                         }
-            
-                        if z == GRID_MAX {
-                            match world.chunks.get(&(self.position + Chunk::FORWARD)) {
-                                Some(chunk) => {
-                                    if chunk.grid[x][y][0] == 0 {
-                                        Chunk::build_face(origin, Face::Back, &mut vertices, &mut indices, block, atlas);
-                                    }
-                                }
-                                None => {
-                                    Chunk::build_face(origin, Face::Back, &mut vertices, &mut indices, block, atlas);
-                                }
+                    }
+                }
+            }
+            grid
+        };
+
+        // The chunks the ground runs through, each with all its neighbors.
+        let (mut naive, mut greedy) = (0, 0);
+        for x in 0..2 {
+            for y in -1..=0 {
+                for z in 0..2 {
+                    let position = Vector3::new(x, y, z);
+                    let mut snapshot = ChunkSnapshot::new(position, [[Color::from_rgb(1.0, 1.0, 1.0); CHUNK_SIZE]; CHUNK_SIZE], true, table());
+                    let light = [[[0; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE];
+                    for dx in -1..=1 {
+                        for dy in -1..=1 {
+                            for dz in -1..=1 {
+                                let offset = Vector3::new(dx, dy, dz);
+                                snapshot.copy_chunk(offset, &terrain(position + offset), &light);
                             }
                         }
-                        else if self.grid[x][y][z+1] == 0 {
-                            Chunk::build_face(origin, Face::Back, &mut vertices, &mut indices, block, atlas);
-                        }
-            
                     }
+
+                    let naive_parts = snapshot.generate_naive_mesh_parts();
+                    let greedy_parts = snapshot.generate_mesh_parts();
+                    naive += naive_parts.vertices.len() + naive_parts.translucent_vertices.len();
+                    greedy += greedy_parts.vertices.len() + greedy_parts.translucent_vertices.len();
                 }
             }
         }
 
-        (vertices, indices)
+        assert!(naive > 0, "no terrain was meshed");
+        assert!(greedy < naive, "greedy meshing made {} vertices against {} naively", greedy, naive);
+    }
+
+    #[test]
+    fn corner_surrounded_by_opaque_blocks_gets_flat_light() {
+        let mut neighborhood = Neighborhood {
+            opaque: [[[true; 3]; 3]; 3],
+            light: [[[[0.0; 4]; 3]; 3]; 3],
+        };
+        neighborhood.light[1][2][1] = [0.5, 0.25, 0.0, 1.0];
+
+        let light = Chunk::vertex_light(&neighborhood, Chunk::UP, Vector3::new(1, 1, 1));
+        assert_eq!(light, [0.5, 0.25, 0.0, 1.0]);
 
-        //self.should_regen_mesh = false;
-        //if vertices.len() == 0 { self.mesh = None; }
-        //else { self.mesh = Some(Mesh::new(device, vertices, indices)); }
+        // With the block above open, only it is averaged.
+        neighborhood.opaque[1][2][1] = false;
+        assert_eq!(Chunk::vertex_light(&neighborhood, Chunk::UP, Vector3::new(1, 1, 1)), [0.5, 0.25, 0.0, 1.0]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn solid_wall_splits_connectivity() {
+        let mut snapshot = snapshot(true);
+        assert_eq!(snapshot.connectivity(), [Chunk::ALL_FACES; 6]);
+
+        // A wall across the whole chunk at x = 8.
+        for y in 0..CHUNK_SIZE as i32 {
+            for z in 0..CHUNK_SIZE as i32 {
+                set(&mut snapshot, 8, y, z, STONE);
+            }
+        }
+
+        // Bits in the order of Face::ALL, left, right, down, up, front and back.
+        let (left, right) = (0, 1);
+        let connectivity = snapshot.connectivity();
+        assert_eq!(connectivity[left], Chunk::ALL_FACES & !(1 << right));
+        assert_eq!(connectivity[right], Chunk::ALL_FACES & !(1 << left));
+        for connected in connectivity.iter().skip(2) {
+            assert_eq!(*connected, Chunk::ALL_FACES);
+        }
+
+        // A gap in the wall joins both sides again.
+        set(&mut snapshot, 8, 3, 3, AIR);
+        assert_eq!(snapshot.connectivity(), [Chunk::ALL_FACES; 6]);
+    }
+}
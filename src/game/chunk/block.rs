@@ -3,12 +3,68 @@ use cgmath::Vector3;
 use image::{DynamicImage, GenericImageView};
 use std::fs;
 
+use crate::game::renderer::vertex::MAX_TILES;
+
 pub struct Block {
     pub id: u32,
     pub name: String,
     pub sided: bool,
+    pub has_front: bool,
+    // Lit states use a front texture of their own, like a burning furnace.
+    pub has_lit_front: bool,
+    pub render: RenderType,
+    // Sides whose texture is multiplied by the world's colormap, like the top of grass.
+    pub tinted: Vec<Side>,
+    // Red, green and blue block light levels given off, 0..15.
+    pub emission: [u8; 3],
+    // Fluids can be moved through, like water and lava.
+    pub fluid: bool,
+    // The color of the fog when the camera is inside the block, 0..255. None for no fog.
+    pub fog: Option<[u8; 3]>,
+
+    pub properties: Vec<Property>,
+    pub default_state: u32,
+    pub state_count: u32,
+}
+
+// How a block is drawn, which also decides which faces next to it get culled.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum RenderType {
+    Opaque,
+    // Fully see-through where the texture's alpha is below half, like leaves.
+    Cutout,
+    // Blended with what's behind it, like water.
+    Translucent,
+}
+
+// A named property of a block, such as 'axis' or 'facing', and every value it can take.
+pub struct Property {
+    pub name: String,
+    pub values: Vec<String>,
+}
+
+// One combination of property values of a block.
+// The id is what gets stored in a chunk's grid.
+pub struct BlockState {
+    pub id: u32,
+    pub block: u32,
+    pub values: Vec<u32>,
+    pub orientation: Orientation,
+    // Whether its 'lit' property is true.
+    pub lit: bool,
+}
+
+// How a state is rotated when it's meshed, derived from its 'axis' or 'facing' property.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Orientation {
+    Default,
+    AxisX,
+    AxisZ,
+    // Quarter turns clockwise around the y axis, starting from north.
+    Facing(u32),
 }
 
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum Side {
     Front,
     Back,
@@ -18,6 +74,14 @@ pub enum Side {
     Bottom,
 }
 
+impl Side {
+    pub const ALL: [Side; 6] = [Side::Front, Side::Back, Side::Left, Side::Right, Side::Top, Side::Bottom];
+
+    pub fn index(&self) -> usize {
+        Side::ALL.iter().position(|side| side == self).unwrap()
+    }
+}
+
 impl Block {
     pub const UP: Vector3<f32> = Vector3::new(0.0, 1.0, 0.0);
     pub const DOWN: Vector3<f32> = Vector3::new(0.0, -1.0, 0.0);
@@ -26,8 +90,160 @@ impl Block {
     pub const FORWARD: Vector3<f32> = Vector3::new(0.0, 0.0, -1.0);
     pub const BACKWARD: Vector3<f32> = Vector3::new(0.0, 0.0, 1.0);
 
+    pub fn new(id: u32, name: String, sided: bool) -> Block {
+        Block {
+            id,
+            name,
+            sided,
+            has_front: false,
+            has_lit_front: false,
+            render: RenderType::Opaque,
+            tinted: Vec::new(),
+            emission: [0, 0, 0],
+            fluid: false,
+            fog: None,
+            properties: Vec::new(),
+            default_state: 0,
+            state_count: 1,
+        }
+    }
+
+    // Whether it can be collided with, anything but air and fluids.
+    pub fn is_solid(&self) -> bool {
+        self.id != 0 && !self.fluid
+    }
+
+    // Reads a block.txt definition, one 'key = value' per line.
+    // 'property name = a, b, c' declares a property, the first value being the default.
+    pub fn parse_definition(&mut self, text: &str) {
+
+        for line in text.lines() {
+
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue; }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => {
+                    println!("invalid line in {} definition: {:?}", self.name, line);
+                    continue;
+                }
+            };
+
+            if let Some(name) = key.strip_prefix("property ") {
+                let values: Vec<String> = value.split(',').map(|v| v.trim().to_string()).filter(|v| !v.is_empty()).collect();
+                if values.is_empty() {
+                    println!("property {} of {} has no values", name, self.name);
+                    continue;
+                }
+                self.properties.push(Property { name: name.trim().to_string(), values });
+            }
+            else if key == "render" {
+                self.render = match value {
+                    "opaque" => RenderType::Opaque,
+                    "cutout" => RenderType::Cutout,
+                    "translucent" => RenderType::Translucent,
+                    _ => {
+                        println!("unknown render type for {}: {:?}", self.name, value);
+                        RenderType::Opaque
+                    }
+                };
+            }
+            else if key == "tint" {
+                for side in value.split(',') {
+                    match side.trim() {
+                        "all" => self.tinted.extend_from_slice(&[Side::Top, Side::Bottom, Side::Front, Side::Back, Side::Left, Side::Right]),
+                        "side" => self.tinted.extend_from_slice(&[Side::Front, Side::Back, Side::Left, Side::Right]),
+                        "top" => self.tinted.push(Side::Top),
+                        "bottom" => self.tinted.push(Side::Bottom),
+                        "front" => self.tinted.push(Side::Front),
+                        "back" => self.tinted.push(Side::Back),
+                        "left" => self.tinted.push(Side::Left),
+                        "right" => self.tinted.push(Side::Right),
+                        side => println!("unknown tint side for {}: {:?}", self.name, side),
+                    }
+                }
+            }
+            // Either a single level for white light, or separate red, green and blue levels.
+            else if key == "emission" {
+                let levels: Result<Vec<u8>, _> = value.split(',').map(|v| v.trim().parse::<u8>()).collect();
+                match levels.as_deref() {
+                    Ok([level]) => self.emission = [*level; 3],
+                    Ok([r, g, b]) => self.emission = [*r, *g, *b],
+                    _ => println!("invalid emission for {}: {:?}", self.name, value),
+                }
+                for level in self.emission.iter_mut() {
+                    *level = (*level).min(15);
+                }
+            }
+            else if key == "fluid" {
+                match value.parse::<bool>() {
+                    Ok(fluid) => self.fluid = fluid,
+                    Err(_) => println!("invalid fluid for {}: {:?}", self.name, value),
+                }
+            }
+            else if key == "fog" {
+                let color: Result<Vec<u8>, _> = value.split(',').map(|v| v.trim().parse::<u8>()).collect();
+                match color.as_deref() {
+                    Ok([r, g, b]) => self.fog = Some([*r, *g, *b]),
+                    _ => println!("invalid fog for {}: {:?}", self.name, value),
+                }
+            }
+            else {
+                println!("unknown key in {} definition: {:?}", self.name, key);
+            }
+        }
+
+        self.state_count = self.properties.iter().map(|p| p.values.len() as u32).product();
+    }
+
+    // Returns the state id with the given property values, any property left out uses its default
+    // and any the block doesn't have is ignored.
+    pub fn get_state(&self, values: &[(&str, &str)]) -> Option<u32> {
+
+        let mut offset = 0;
+        let mut stride = 1;
+
+        for property in &self.properties {
+            let mut index = 0;
+            for (name, value) in values {
+                if property.name == *name {
+                    index = property.values.iter().position(|v| v == value)?;
+                }
+            }
+            offset += index as u32 * stride;
+            stride *= property.values.len() as u32;
+        }
+
+        Some(self.default_state + offset)
+    }
+
+    // The state a block gets when it's placed against a face with the given normal, by a player looking along 'forward'.
+    // Logs run along the normal, and blocks with a facing turn their front towards the player.
+    pub fn get_placement_state(&self, normal: Vector3<i32>, forward: Vector3<f32>) -> u32 {
+
+        let axis = if normal.x != 0 { "x" } else if normal.z != 0 { "z" } else { "y" };
+        let facing = if forward.x.abs() > forward.z.abs() {
+            if forward.x > 0.0 { "west" } else { "east" }
+        }
+        else if forward.z > 0.0 { "north" } else { "south" };
+
+        self.get_state(&[("axis", axis), ("facing", facing)]).unwrap_or(self.default_state)
+    }
+
+    // Rotates texture coordinates by a number of quarter turns.
+    pub fn rotate_tex_coords(coords: ([f32; 2], [f32; 2], [f32; 2], [f32; 2]), turns: u32) -> ([f32; 2], [f32; 2], [f32; 2], [f32; 2]) {
+        let (a, b, c, d) = coords;
+        match turns % 4 {
+            1 => (b, c, d, a),
+            2 => (c, d, a, b),
+            3 => (d, a, b, c),
+            _ => (a, b, c, d),
+        }
+    }
+
     // Ordered CCW, from top right.
-    pub fn get_tex_coords(&self, side: Side, atlas: &DynamicImage) -> ([f32; 2], [f32; 2], [f32; 2], [f32; 2]) {
+    pub fn get_tex_coords(&self, side: Side, lit: bool, atlas: &DynamicImage) -> ([f32; 2], [f32; 2], [f32; 2], [f32; 2]) {
         
         let tl: [f32; 2];
         let tr: [f32; 2];
@@ -52,6 +268,18 @@ impl Block {
                     bl = [x_increment + x_increment + x_increment, y_increment * self.id as f32 + y_increment];
                     br = [x_increment + x_increment + x_increment + x_increment, y_increment * self.id as f32 + y_increment];
                 },
+                Side::Front if self.has_lit_front && lit => {
+                    tl = [x_increment * 5.0, y_increment * self.id as f32];
+                    tr = [x_increment * 6.0, y_increment * self.id as f32];
+                    bl = [x_increment * 5.0, y_increment * self.id as f32 + y_increment];
+                    br = [x_increment * 6.0, y_increment * self.id as f32 + y_increment];
+                },
+                Side::Front if self.has_front => {
+                    tl = [x_increment * 4.0, y_increment * self.id as f32];
+                    tr = [x_increment * 5.0, y_increment * self.id as f32];
+                    bl = [x_increment * 4.0, y_increment * self.id as f32 + y_increment];
+                    br = [x_increment * 5.0, y_increment * self.id as f32 + y_increment];
+                },
                 Side::Front => {
                     tl = [x_increment + x_increment, y_increment * self.id as f32];
                     tr = [x_increment + x_increment + x_increment, y_increment * self.id as f32];
@@ -91,8 +319,66 @@ impl Block {
 
 }
 
+impl Orientation {
+
+    pub fn from_values(block: &Block, values: &[u32]) -> Orientation {
+
+        for (property, value) in block.properties.iter().zip(values) {
+            let value = property.values[*value as usize].as_str();
+            match (property.name.as_str(), value) {
+                ("axis", "x") => return Orientation::AxisX,
+                ("axis", "z") => return Orientation::AxisZ,
+                ("facing", "east") => return Orientation::Facing(1),
+                ("facing", "south") => return Orientation::Facing(2),
+                ("facing", "west") => return Orientation::Facing(3),
+                _ => (),
+            }
+        }
+
+        Orientation::Default
+    }
+
+    // Returns which of the block's own sides ends up facing the given side of the world,
+    // and how many quarter turns its texture has to be rotated by.
+    pub fn orient(&self, side: Side) -> (Side, u32) {
+
+        const HORIZONTAL: [Side; 4] = [Side::Front, Side::Right, Side::Back, Side::Left];
+
+        match self {
+            Orientation::Default => (side, 0),
+
+            // Top points along +x.
+            Orientation::AxisX => match side {
+                Side::Right => (Side::Top, 0),
+                Side::Left => (Side::Bottom, 0),
+                Side::Top => (Side::Left, 1),
+                Side::Bottom => (Side::Right, 1),
+                Side::Front | Side::Back => (side, 1),
+            },
+
+            // Top points along -z.
+            Orientation::AxisZ => match side {
+                Side::Front => (Side::Top, 0),
+                Side::Back => (Side::Bottom, 0),
+                Side::Top => (Side::Back, 0),
+                Side::Bottom => (Side::Front, 0),
+                Side::Left | Side::Right => (side, 1),
+            },
+
+            Orientation::Facing(turns) => match side {
+                Side::Top | Side::Bottom => (side, *turns),
+                _ => {
+                    let index = HORIZONTAL.iter().position(|s| *s == side).unwrap() as u32;
+                    (HORIZONTAL[((index + 4 - turns % 4) % 4) as usize], 0)
+                }
+            },
+        }
+    }
+}
+
 pub struct BlockList {
     pub blocks: Vec<Block>,
+    pub states: Vec<BlockState>,
     pub atlas: DynamicImage,
 }
 
@@ -104,9 +390,12 @@ impl BlockList {
         let block_count = fs::read_dir("./src/game/data/blocks").unwrap().count() as u32;
         let mut block_vec: Vec<Block> = Vec::new();
 
-        block_vec.push(Block { id: 0, name: String::from("air"), sided: false });
+        block_vec.push(Block::new(0, String::from("air"), false));
+
+        // Each block gets a row of six tiles, and chunk vertices only have room for so many.
+        assert!(block_count * 6 <= MAX_TILES, "{} blocks need more atlas tiles than chunk vertices can address", block_count);
 
-        let mut atlas_buf: image::ImageBuffer<image::Rgba<u8>, Vec<u8>> = image::ImageBuffer::new(64, block_count * 16);
+        let mut atlas_buf: image::ImageBuffer<image::Rgba<u8>, Vec<u8>> = image::ImageBuffer::new(96, block_count * 16);
         for (_x, _y, pixel) in atlas_buf.enumerate_pixels_mut() {
             *pixel = image::Rgba([255 as u8, 255 as u8, 255 as u8, 255 as u8]);
         }
@@ -122,7 +411,9 @@ impl BlockList {
                         let dir = fs::read_dir(block_folder.path()).unwrap();
                         println!("registered block: {:?}", block_folder.file_name());
                         let mut sided = true;
-                        
+                        let mut has_front = false;
+                        let mut has_lit_front = false;
+                        let mut definition: Option<String> = None;
 
                         for path in dir {
 
@@ -147,6 +438,19 @@ impl BlockList {
                                 image = Some(image::open(file.path()).unwrap());
                                 x_offset = 3;
                             }
+                            else if file.file_name() == "front.png" {
+                                image = Some(image::open(file.path()).unwrap());
+                                x_offset = 4;
+                                has_front = true;
+                            }
+                            else if file.file_name() == "front_lit.png" {
+                                image = Some(image::open(file.path()).unwrap());
+                                x_offset = 5;
+                                has_lit_front = true;
+                            }
+                            else if file.file_name() == "block.txt" {
+                                definition = Some(fs::read_to_string(file.path()).unwrap());
+                            }
                             
                             match image {
                                 Some(img) => {
@@ -161,7 +465,14 @@ impl BlockList {
 
                         }
 
-                        block_vec.push(Block { id: y_offset, name: block_folder.file_name().to_str().unwrap().into(), sided });
+                        let mut block = Block::new(y_offset, block_folder.file_name().to_str().unwrap().into(), sided);
+                        block.has_front = has_front;
+                        block.has_lit_front = has_lit_front;
+                        if let Some(text) = definition {
+                            block.parse_definition(&text);
+                        }
+
+                        block_vec.push(block);
                         y_offset += 1;
                     }
                 }
@@ -177,13 +488,43 @@ impl BlockList {
             }
         }
 
+        let states = BlockList::build_states(&mut block_vec);
+
         BlockList {
             blocks: block_vec,
+            states,
             atlas: image::open("./src/game/data/blocks/atlas.png").unwrap(),
         }
         
     }
 
+    // Every block gets a contiguous range of state ids, air being state 0.
+    // The first property changes fastest between neighboring ids.
+    pub fn build_states(blocks: &mut [Block]) -> Vec<BlockState> {
+
+        let mut states: Vec<BlockState> = Vec::new();
+        for block in blocks {
+
+            block.default_state = states.len() as u32;
+            for offset in 0..block.state_count {
+
+                let mut values = Vec::new();
+                let mut rest = offset;
+                for property in &block.properties {
+                    let count = property.values.len() as u32;
+                    values.push(rest % count);
+                    rest /= count;
+                }
+
+                let orientation = Orientation::from_values(block, &values);
+                let lit = block.properties.iter().zip(&values).any(|(p, v)| p.name == "lit" && p.values[*v as usize] == "true");
+                states.push(BlockState { id: block.default_state + offset, block: block.id, values, orientation, lit });
+            }
+        }
+
+        states
+    }
+
     pub fn get_block(&self, name: &str) -> Option<&Block> {
 
         for (i, block) in self.blocks.iter().enumerate() {
@@ -195,4 +536,287 @@ impl BlockList {
         None
     }
 
-}
\ No newline at end of file
+    pub fn get_state(&self, state: u32) -> Option<&BlockState> {
+        self.states.get(state as usize)
+    }
+
+    pub fn get_block_from_state(&self, state: u32) -> Option<&Block> {
+        match self.states.get(state as usize) {
+            Some(state) => self.blocks.get(state.block as usize),
+            None => None,
+        }
+    }
+
+    // Whether a state blocks light and hides the faces behind it.
+    pub fn is_opaque(&self, state: u32) -> bool {
+        match self.get_block_from_state(state) {
+            Some(block) => block.id != 0 && block.render == RenderType::Opaque,
+            None => false,
+        }
+    }
+
+    pub fn get_emission(&self, state: u32) -> [u8; 3] {
+        match self.get_block_from_state(state) {
+            Some(block) => block.emission,
+            None => [0, 0, 0],
+        }
+    }
+
+    // Returns the value a state has for one of its block's properties.
+    pub fn get_state_value(&self, state: u32, property: &str) -> Option<&str> {
+
+        let state = self.get_state(state)?;
+        let block = self.blocks.get(state.block as usize)?;
+
+        for (p, value) in block.properties.iter().zip(&state.values) {
+            if p.name == property {
+                return Some(p.values[*value as usize].as_str());
+            }
+        }
+
+        None
+    }
+
+    // Returns the state of the same block with one property changed, None if the block doesn't have it.
+    pub fn with_value(&self, state: u32, property: &str, value: &str) -> Option<u32> {
+
+        let block = self.get_block_from_state(state)?;
+        if !block.properties.iter().any(|p| p.name == property) { return None; }
+
+        let mut values: Vec<(&str, &str)> = Vec::new();
+        for p in &block.properties {
+            let current = if p.name == property { value } else { self.get_state_value(state, &p.name)? };
+            values.push((p.name.as_str(), current));
+        }
+
+        block.get_state(&values)
+    }
+
+    // The block's name followed by the state's property values, like 'log[axis=x]'.
+    pub fn describe_state(&self, state: u32) -> String {
+
+        let block = match self.get_block_from_state(state) {
+            Some(block) => block,
+            None => return format!("unknown state {}", state),
+        };
+        if block.properties.is_empty() { return block.name.clone(); }
+
+        let values: Vec<String> = block.properties.iter()
+            .filter_map(|p| self.get_state_value(state, &p.name).map(|value| format!("{}={}", p.name, value)))
+            .collect();
+        format!("{}[{}]", block.name, values.join(", "))
+    }
+
+}
+
+// How one side of a state looks, see BlockTable.
+#[derive(Copy, Clone, Debug)]
+pub struct SideTexture {
+    // Texture coordinates of the face's corners within one block, 0 or 1, in the order of Chunk::face_corners.
+    pub tex_coords: [[f32; 2]; 4],
+    // Tiles are numbered along each row of the atlas, one row after another.
+    pub tile: u32,
+    pub tinted: bool,
+}
+
+// Everything meshing needs to know about a state.
+#[derive(Copy, Clone, Debug)]
+pub struct StateProperties {
+    pub block: u32,
+    pub render: RenderType,
+    pub opaque: bool,
+    // For each side of the world, in the order of Side::ALL, with the state's orientation already applied.
+    pub sides: [SideTexture; 6],
+}
+
+// The properties of every state, indexed by state id, worked out once from the block list and atlas
+// so meshing doesn't have to look at either.
+pub struct BlockTable {
+    pub states: Vec<StateProperties>,
+}
+
+impl BlockTable {
+
+    // Stands in for blocks of neighboring chunks that aren't loaded. Faces against it are kept and it's not opaque.
+    pub const UNLOADED: u32 = u32::MAX;
+
+    pub fn new(block_list: &BlockList) -> BlockTable {
+
+        let mut states = Vec::new();
+        for state in &block_list.states {
+
+            let block = &block_list.blocks[state.block as usize];
+            let mut sides = [SideTexture { tex_coords: [[0.0; 2]; 4], tile: 0, tinted: false }; 6];
+            for (texture, side) in sides.iter_mut().zip(Side::ALL.iter()) {
+                *texture = BlockTable::side_texture(block, state, *side, &block_list.atlas);
+            }
+
+            states.push(StateProperties {
+                block: block.id,
+                render: block.render,
+                opaque: block_list.is_opaque(state.id),
+                sides,
+            });
+        }
+
+        BlockTable { states }
+    }
+
+    fn side_texture(block: &Block, state: &BlockState, side: Side, atlas: &DynamicImage) -> SideTexture {
+
+        let (side, turns) = state.orientation.orient(side);
+        let (a, b, c, d) = Block::rotate_tex_coords(block.get_tex_coords(side, state.lit, atlas), turns);
+        let coords = [a, b, c, d];
+
+        let min_u = coords.iter().map(|t| t[0]).fold(f32::MAX, f32::min);
+        let min_v = coords.iter().map(|t| t[1]).fold(f32::MAX, f32::min);
+        let size_u = coords.iter().map(|t| t[0]).fold(f32::MIN, f32::max) - min_u;
+        let size_v = coords.iter().map(|t| t[1]).fold(f32::MIN, f32::max) - min_v;
+
+        let mut tex_coords = [[0.0; 2]; 4];
+        for (i, t) in coords.iter().enumerate() {
+            tex_coords[i] = [
+                if t[0] > min_u + size_u * 0.5 { 1.0 } else { 0.0 },
+                if t[1] > min_v + size_v * 0.5 { 1.0 } else { 0.0 },
+            ];
+        }
+
+        let columns = (1.0 / size_u).round() as u32;
+        let tile = (min_v / size_v).round() as u32 * columns + (min_u / size_u).round() as u32;
+
+        SideTexture { tex_coords, tile, tinted: block.tinted.contains(&side) }
+    }
+
+    pub fn get(&self, state: u32) -> &StateProperties {
+        &self.states[state as usize]
+    }
+
+    pub fn is_opaque(&self, state: u32) -> bool {
+        state != BlockTable::UNLOADED && self.get(state).opaque
+    }
+
+    // Whether the face of a block is visible when it's up against the given neighbor.
+    // Faces are hidden by opaque neighbors, and translucent blocks hide their faces against each other.
+    pub fn is_face_visible(&self, state: u32, neighbor: u32) -> bool {
+
+        if neighbor == 0 || neighbor == BlockTable::UNLOADED { return true; }
+
+        let neighbor = self.get(neighbor);
+        match neighbor.render {
+            RenderType::Opaque => false,
+            RenderType::Cutout => true,
+            RenderType::Translucent => neighbor.block != self.get(state).block,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Air, a log and a furnace, with an atlas of blank tiles in their place, without reading the block folder.
+    fn block_list() -> BlockList {
+
+        let mut log = Block::new(1, String::from("log"), true);
+        log.parse_definition("property axis = y, x, z");
+
+        let mut furnace = Block::new(2, String::from("furnace"), true);
+        furnace.has_front = true;
+        furnace.has_lit_front = true;
+        furnace.parse_definition("property facing = north, east, south, west\nproperty lit = false, true");
+
+        let mut blocks = vec![Block::new(0, String::from("air"), false), log, furnace];
+        let states = BlockList::build_states(&mut blocks);
+        BlockList { blocks, states, atlas: DynamicImage::new_rgba8(96, 48) }
+    }
+
+    #[test]
+    fn state_ids_round_trip_through_their_values() {
+        let list = block_list();
+        let furnace = list.get_block("furnace").unwrap();
+        assert_eq!(furnace.state_count, 8);
+        assert_eq!(list.states.len(), 1 + 3 + 8);
+
+        for state in furnace.default_state..furnace.default_state + furnace.state_count {
+            let facing = list.get_state_value(state, "facing").unwrap();
+            let lit = list.get_state_value(state, "lit").unwrap();
+            assert_eq!(furnace.get_state(&[("facing", facing), ("lit", lit)]), Some(state));
+            assert_eq!(list.get_block_from_state(state).unwrap().name, "furnace");
+        }
+
+        // Left out properties use their default, and values the block doesn't have give nothing.
+        assert_eq!(furnace.get_state(&[]), Some(furnace.default_state));
+        assert_eq!(furnace.get_state(&[("facing", "up")]), None);
+        assert_eq!(list.describe_state(furnace.get_state(&[("facing", "west"), ("lit", "true")]).unwrap()), "furnace[facing=west, lit=true]");
+
+        let lit = list.with_value(furnace.default_state, "lit", "true").unwrap();
+        assert_eq!(list.describe_state(lit), "furnace[facing=north, lit=true]");
+        assert!(list.get_state(lit).unwrap().lit);
+        assert_eq!(list.with_value(furnace.default_state, "axis", "x"), None);
+    }
+
+    #[test]
+    fn orientation_follows_axis_and_facing() {
+        let list = block_list();
+        let log = list.get_block("log").unwrap();
+        let furnace = list.get_block("furnace").unwrap();
+
+        let orientation = |state: Option<u32>| list.get_state(state.unwrap()).unwrap().orientation;
+        assert_eq!(orientation(log.get_state(&[("axis", "y")])), Orientation::Default);
+        assert_eq!(orientation(log.get_state(&[("axis", "x")])), Orientation::AxisX);
+        assert_eq!(orientation(log.get_state(&[("axis", "z")])), Orientation::AxisZ);
+        assert_eq!(orientation(furnace.get_state(&[("facing", "east"), ("lit", "true")])), Orientation::Facing(1));
+
+        // A log along x shows its rings on the sides of the world along x, and its bark turned a quarter on top.
+        assert_eq!(Orientation::AxisX.orient(Side::Right), (Side::Top, 0));
+        assert_eq!(Orientation::AxisX.orient(Side::Top), (Side::Left, 1));
+        assert_eq!(Orientation::AxisZ.orient(Side::Front), (Side::Top, 0));
+
+        // Facing east puts the front on the right, and facing south puts it at the back.
+        assert_eq!(Orientation::Facing(1).orient(Side::Right), (Side::Front, 0));
+        assert_eq!(Orientation::Facing(2).orient(Side::Back), (Side::Front, 0));
+        assert_eq!(Orientation::Facing(3).orient(Side::Top), (Side::Top, 3));
+    }
+
+    #[test]
+    fn placing_turns_logs_along_the_normal_and_fronts_towards_the_player() {
+        let list = block_list();
+        let log = list.get_block("log").unwrap();
+        let furnace = list.get_block("furnace").unwrap();
+        let forward = Vector3::new(1.0, -0.5, 0.2);
+
+        assert_eq!(list.describe_state(log.get_placement_state(Vector3::new(-1, 0, 0), forward)), "log[axis=x]");
+        assert_eq!(list.describe_state(log.get_placement_state(Vector3::new(0, 1, 0), forward)), "log[axis=y]");
+        assert_eq!(list.describe_state(log.get_placement_state(Vector3::new(0, 0, 1), forward)), "log[axis=z]");
+
+        // Looking along +x, the front has to face -x to be seen.
+        assert_eq!(list.describe_state(furnace.get_placement_state(Vector3::new(0, 1, 0), forward)), "furnace[facing=west, lit=false]");
+        assert_eq!(list.describe_state(furnace.get_placement_state(Vector3::new(0, 1, 0), Vector3::new(0.1, 0.0, -1.0))), "furnace[facing=south, lit=false]");
+    }
+
+    #[test]
+    fn table_rotates_textures_per_state() {
+        let list = block_list();
+        let table = BlockTable::new(&list);
+        let log = list.get_block("log").unwrap();
+        let furnace = list.get_block("furnace").unwrap();
+
+        // Six tiles to a row and a row per block, so the log's top is tile 7 and its side tile 8.
+        let (top, right) = (Side::Top.index(), Side::Right.index());
+        let upright = table.get(log.default_state);
+        assert_eq!((upright.sides[top].tile, upright.sides[right].tile), (7, 8));
+
+        let along_x = table.get(log.get_state(&[("axis", "x")]).unwrap());
+        assert_eq!((along_x.sides[top].tile, along_x.sides[right].tile), (8, 7));
+
+        // The bark on top is the bark on the side turned a quarter.
+        let [a, b, c, d] = upright.sides[right].tex_coords;
+        assert_eq!(along_x.sides[top].tex_coords, [b, c, d, a]);
+
+        // A lit furnace facing east shows its lit front on the right of the world.
+        let lit_east = table.get(furnace.get_state(&[("facing", "east"), ("lit", "true")]).unwrap());
+        assert_eq!(lit_east.sides[right].tile, 2 * 6 + 5);
+        assert_eq!(lit_east.sides[Side::Front.index()].tile, 2 * 6 + 2);
+        assert_eq!(table.get(furnace.get_state(&[("facing", "east")]).unwrap()).sides[right].tile, 2 * 6 + 4);
+    }
+}
@@ -1,86 +1,169 @@
+use cgmath::Vector3;
 
-use cgmath::{MetricSpace, Vector3};
-
-pub struct CollisionInfo {
+// How far apart two boxes have to overlap to count, so boxes resting against each other don't.
+const EPSILON: f32 = 0.0001;
 
+// An axis aligned box, from its lowest corner to its highest.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct BoxCollider {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
 }
 
-pub struct CircleCollider {
-    pub position: Vector3<f32>,
-    pub radius: f32,
-}
+impl BoxCollider {
 
-impl CircleCollider {
-    pub fn new(position: Vector3<f32>, radius: f32) -> CircleCollider {
-        CircleCollider {
-            position: position,
-            radius
-        }
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> BoxCollider {
+        BoxCollider { min, max }
     }
-}
 
-pub struct CubeCollider {
-    pub position: Vector3<f32>,
-    pub size: Vector3<f32>,
-}
+    // A box 'size' across, centered on 'position' horizontally and standing on it.
+    pub fn standing(position: Vector3<f32>, size: Vector3<f32>) -> BoxCollider {
+        let half = Vector3::new(size.x / 2.0, 0.0, size.z / 2.0);
+        BoxCollider::new(position - half, position + half + Vector3::new(0.0, size.y, 0.0))
+    }
 
-impl CubeCollider {
-    pub fn new(position: Vector3<f32>, size: Vector3<f32>) -> CubeCollider {
-        CubeCollider {
-            position,
-            size,
-        }
+    // The space a block in the grid takes up. Blocks span -1 on the z axis from their position.
+    pub fn block(position: Vector3<i32>) -> BoxCollider {
+        let p = Vector3::new(position.x as f32, position.y as f32, position.z as f32);
+        BoxCollider::new(p - Vector3::new(0.0, 0.0, 1.0), p + Vector3::new(1.0, 1.0, 0.0))
+    }
+
+    pub fn translated(&self, offset: Vector3<f32>) -> BoxCollider {
+        BoxCollider::new(self.min + offset, self.max + offset)
     }
-}
 
-#[allow(dead_code)]
-pub fn circle_circle(c0: &CircleCollider, c1: &CircleCollider) -> Option<CollisionInfo> {
-    
-    // Just check the sum of the two circle's radii and make sure they're on the same y-level.
-    let distance = c0.position.distance(c1.position);
-    if distance <= c0.radius + c1.radius && c0.position.y == c1.position.y {
-        Some(CollisionInfo { })
+    fn overlaps_on(&self, other: &BoxCollider, axis: usize) -> bool {
+        self.min[axis] < other.max[axis] - EPSILON && other.min[axis] < self.max[axis] - EPSILON
     }
-    else {
-        None
+
+    pub fn intersects(&self, other: &BoxCollider) -> bool {
+        (0..3).all(|axis| self.overlaps_on(other, axis))
     }
 
+    // Every block in the grid the box might touch, a block further out on every side to be safe.
+    fn blocks(&self) -> impl Iterator<Item = Vector3<i32>> {
+        let (min, max) = (self.min, self.max);
+        let range = move |axis: usize| (min[axis].floor() as i32 - 1)..=(max[axis].ceil() as i32 + 1);
+        range(0).flat_map(move |x| range(1).flat_map(move |y| range(2).map(move |z| Vector3::new(x, y, z))))
+    }
 }
 
-pub fn circle_cube(circle: &CircleCollider, cube: &CubeCollider) -> Option<CollisionInfo> {
+// Moves a box by 'motion' one axis at a time, y first then x and z, stopping it short of any block 'is_solid' returns true for.
+// Blocks the box is already inside are ignored so it can always get back out.
+// Returns how far it actually moved, and on which axes it was stopped.
+pub fn sweep_box<F: Fn(Vector3<i32>) -> bool>(collider: BoxCollider, motion: Vector3<f32>, is_solid: F) -> (Vector3<f32>, [bool; 3]) {
 
-    // If the circle's y-value is within the cube's y extents.
-    if circle.position.y >= cube.position.y && circle.position.y < cube.position.y + cube.size.y {
+    let mut collider = collider;
+    let mut moved = Vector3::new(0.0, 0.0, 0.0);
+    let mut blocked = [false; 3];
 
-        let mut test_pos = circle.position;
+    for axis in [1, 0, 2].iter().cloned() {
 
-        if circle.position.x < cube.position.x {
-            test_pos.x = cube.position.x;
-        }
-        else if circle.position.x > cube.position.x + cube.size.x {
-            test_pos.x = cube.position.x + cube.size.x;
-        }
+        let distance = motion[axis];
+        if distance == 0.0 { continue; }
 
-        if circle.position.z > cube.position.z {
-            test_pos.z = cube.position.z;
-        }
-        else if circle.position.z < cube.position.z + cube.size.z {
-            test_pos.z = cube.position.z + cube.size.z;
+        let mut offset = Vector3::new(0.0, 0.0, 0.0);
+        offset[axis] = distance;
+        let swept = BoxCollider::new(
+            Vector3::new(collider.min.x.min(collider.min.x + offset.x), collider.min.y.min(collider.min.y + offset.y), collider.min.z.min(collider.min.z + offset.z)),
+            Vector3::new(collider.max.x.max(collider.max.x + offset.x), collider.max.y.max(collider.max.y + offset.y), collider.max.z.max(collider.max.z + offset.z)),
+        );
+
+        let mut allowed = distance;
+        for position in swept.blocks() {
+
+            let block = BoxCollider::block(position);
+            if !swept.intersects(&block) || collider.intersects(&block) || !is_solid(position) { continue; }
+
+            if distance > 0.0 {
+                allowed = allowed.min((block.min[axis] - collider.max[axis]).max(0.0));
+            }
+            else {
+                allowed = allowed.max((block.max[axis] - collider.min[axis]).min(0.0));
+            }
         }
 
-        let distance = circle.position.distance(test_pos);
-        if distance <= circle.radius {
-            Some(CollisionInfo {})
+        if allowed != distance {
+            blocked[axis] = true;
         }
-        else {
-            None
+
+        moved[axis] = allowed;
+        offset[axis] = allowed;
+        collider = collider.translated(offset);
+    }
+
+    (moved, blocked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(x: f32, y: f32, z: f32) -> BoxCollider {
+        BoxCollider::standing(Vector3::new(x, y, z), Vector3::new(0.6, 1.8, 0.6))
+    }
+
+    fn assert_moved(moved: Vector3<f32>, expected: Vector3<f32>) {
+        for axis in 0..3 {
+            assert!((moved[axis] - expected[axis]).abs() < 0.0001, "moved {:?}, expected {:?}", moved, expected);
         }
+    }
 
+    #[test]
+    fn walking_into_a_wall() {
+        // A wall filling x = 2..3, the player's body spanning x = 1.2..1.8.
+        let wall = |p: Vector3<i32>| p.x == 2;
 
+        let (moved, blocked) = sweep_box(player(1.5, 1.0, -0.5), Vector3::new(0.5, 0.0, 0.0), wall);
+        assert_moved(moved, Vector3::new(0.2, 0.0, 0.0));
+        assert_eq!(blocked, [true, false, false]);
+
+        // Walking away isn't stopped.
+        let (moved, blocked) = sweep_box(player(1.5, 1.0, -0.5), Vector3::new(-0.5, 0.0, 0.0), wall);
+        assert_moved(moved, Vector3::new(-0.5, 0.0, 0.0));
+        assert_eq!(blocked, [false, false, false]);
+
+        // Blocks at grid z 1 fill z = 0..1, the player's body spanning z = -0.8..-0.2.
+        let (moved, blocked) = sweep_box(player(0.5, 1.0, -0.5), Vector3::new(0.0, 0.0, 0.5), |p| p.z == 1);
+        assert_moved(moved, Vector3::new(0.0, 0.0, 0.2));
+        assert_eq!(blocked, [false, false, true]);
     }
-    else {
-        None
+
+    #[test]
+    fn jumping_into_a_ceiling() {
+        // A ceiling filling y = 3..4, the player's head at 2.8.
+        let (moved, blocked) = sweep_box(player(0.5, 1.0, -0.5), Vector3::new(0.1, 0.5, 0.0), |p| p.y == 3);
+        assert_moved(moved, Vector3::new(0.1, 0.2, 0.0));
+        assert_eq!(blocked, [false, true, false]);
     }
 
-}
+    #[test]
+    fn sliding_into_an_inside_corner() {
+        // Walls filling x = 2..3 and z = 0..1, meeting in a corner.
+        let corner = |p: Vector3<i32>| p.x == 2 || p.z == 1;
+        let (moved, blocked) = sweep_box(player(1.5, 1.0, -0.5), Vector3::new(0.5, 0.0, 0.5), corner);
+        assert_moved(moved, Vector3::new(0.2, 0.0, 0.2));
+        assert_eq!(blocked, [true, false, true]);
+
+        // Sliding along one wall of the corner is only stopped by the other.
+        let (moved, blocked) = sweep_box(player(1.0, 1.0, -0.5), Vector3::new(0.1, 0.0, 0.5), corner);
+        assert_moved(moved, Vector3::new(0.1, 0.0, 0.2));
+        assert_eq!(blocked, [false, false, true]);
+    }
 
+    #[test]
+    fn landing_on_a_block_edge() {
+        // A single block filling x = 0..1, y = 0..1, z = -1..0.
+        let block = |p: Vector3<i32>| p == Vector3::new(0, 0, 0);
+
+        // Just overlapping its edge, the body spanning x = 0.95..1.55.
+        let (moved, blocked) = sweep_box(player(1.25, 1.2, -0.5), Vector3::new(0.0, -0.5, 0.0), block);
+        assert_moved(moved, Vector3::new(0.0, -0.2, 0.0));
+        assert_eq!(blocked, [false, true, false]);
+
+        // Just past it, the body spanning x = 1.05..1.65, falling by.
+        let (moved, blocked) = sweep_box(player(1.35, 1.2, -0.5), Vector3::new(0.0, -0.5, 0.0), block);
+        assert_moved(moved, Vector3::new(0.0, -0.5, 0.0));
+        assert_eq!(blocked, [false, false, false]);
+    }
+}
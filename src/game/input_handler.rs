@@ -266,6 +266,12 @@ impl InputMap {
     pub fn post_update(&mut self) {
         self.mouse.delta = cgmath::Vector2::new(0.0, 0.0);
         self.mouse.wheel_delta = 0.0;
+
+        // Buttons are only just pressed or released for the frame it happened in.
+        for button in [&mut self.mouse.lmb, &mut self.mouse.mmb, &mut self.mouse.rmb].iter_mut() {
+            button.just_pressed = false;
+            button.just_released = false;
+        }
     }
 
     pub fn get_key(&mut self, key: VirtualKeyCode) -> State {
@@ -7,6 +7,11 @@ use super::input_handler::InputMap;
 use super::camera::{self, Camera};
 use winit::event::VirtualKeyCode as Key;
 
+// Width, height and depth of the player's body, standing on its position.
+const PLAYER_SIZE: Vector3<f32> = Vector3::new(0.6, 1.8, 0.6);
+// How far away the player can look at and reach blocks from.
+pub const REACH: f32 = 8.0;
+
 pub struct Player {
 
     pub camera: super::camera::Camera,
@@ -48,11 +53,6 @@ impl Player {
         }
     }
 
-    pub fn get_block_pos(&self, position: Vector3<f32>) -> Vector3<i32> {
-        let p = position;
-        Vector3::new(p.x.floor() as i32, p.y.floor() as i32, p.z.ceil() as i32)
-    }
-
     pub fn update(&mut self, input: &mut InputMap, world: &World) {
 
         if input.get_key(Key::F).just_pressed {
@@ -79,67 +79,23 @@ impl Player {
         if xz_dir.magnitude() != 0.0 { xz_dir = xz_dir.normalize(); }
         let mut velocity = xz_dir * speed;
         
-        let mut is_on_floor = false;
+        let motion;
 
         if !self.freecam_mode {
 
             self.gravity_vel += Vector3::new(0.0, -self.gravity, 0.0);
-    
-            let x_collider = collision::CircleCollider::new(self.position + Vector3::new(velocity.x, 0.0, 0.0), 0.25);
-            let z_collider = collision::CircleCollider::new(self.position + Vector3::new(0.0, 0.0, velocity.z), 0.25);
-            let gravity_collider = collision::CircleCollider::new(self.position + self.gravity_vel, 0.25);
-            for x in -2..2 {
-                for z in -2..2 {
-    
-                    let gravity_block_position = self.get_block_pos(self.position + self.gravity_vel) + Vector3::new(x, 0, z);
-                    let x_block_position = self.get_block_pos(self.position + Vector3::new(velocity.x, 0.0, 0.0)) + Vector3::new(x, 0, z);
-                    let z_block_position = self.get_block_pos(self.position + Vector3::new(0.0, 0.0, velocity.z)) + Vector3::new(x, 0, z);
-    
-                    let gravity_block = world.get_block_at(gravity_block_position);
-                    let x_block = world.get_block_at(x_block_position);
-                    let z_block = world.get_block_at(z_block_position);
-    
-                    // y axis
-                    if gravity_block.is_some() && gravity_block.unwrap().id != 0 {
-                        let cube_collider = collision::CubeCollider::new(Vector3::new(
-                            gravity_block_position.x as f32, 
-                            gravity_block_position.y as f32, 
-                            gravity_block_position.z as f32
-                        ), Vector3::new(1.0, 1.0, -1.0));
-    
-                        if collision::circle_cube(&gravity_collider, &cube_collider).is_some() {
-                            self.position.y = self.position.y.round();
-                            self.gravity_vel.y = 0.0;
-                            is_on_floor = true;
-                        }
-                    }
-    
-                    // x axis
-                    if x_block.is_some() && x_block.unwrap().id != 0 {
-                        let cube_collider = collision::CubeCollider::new(Vector3::new (
-                            x_block_position.x as f32,
-                            x_block_position.y as f32,
-                            x_block_position.z as f32,
-                        ), Vector3::new(1.0, 1.0, -1.0));
-    
-                        if collision::circle_cube(&x_collider, &cube_collider).is_some() {
-                            velocity.x = 0.0;
-                        }
-                    }
-    
-                    // z axis
-                    if z_block.is_some() && z_block.unwrap().id != 0 {
-                        let cube_collider = collision::CubeCollider::new(Vector3::new(
-                            z_block_position.x as f32,
-                            z_block_position.y as f32,
-                            z_block_position.z as f32,
-                        ), Vector3::new(1.0, 1.0, -1.0));
-    
-                        if collision::circle_cube(&z_collider, &cube_collider).is_some() {
-                            velocity.z = 0.0;
-                        }
-                    }
-                }
+
+            // Blocks of chunks that aren't loaded yet are walked through.
+            let collider = collision::BoxCollider::standing(self.position, PLAYER_SIZE);
+            let (moved, blocked) = collision::sweep_box(collider, velocity + self.gravity_vel, |position| {
+                world.get_block_at(position).is_some_and(|block| block.is_solid())
+            });
+            motion = moved;
+
+            // Landing or hitting a ceiling stops the fall or jump.
+            let is_on_floor = blocked[1] && self.gravity_vel.y < 0.0;
+            if blocked[1] {
+                self.gravity_vel.y = 0.0;
             }
 
             if is_on_floor && input.get_key(Key::Space).held {
@@ -160,9 +116,10 @@ impl Player {
             if input.get_key(Key::LShift).held {
                 velocity -= up * speed;
             }
+            motion = velocity;
         }
    
-        self.position += self.gravity_vel + velocity;
+        self.position += motion;
         self.camera.position = self.position + Vector3::new(0.0, 1.5, 0.0);
 
         let mouse_x = input.mouse.delta.x;
@@ -0,0 +1,255 @@
+use std::{cell::RefCell, collections::VecDeque, fs, time::{Duration, Instant}};
+
+// How many frames averages and percentiles are taken over, and how many get exported.
+const HISTORY: usize = 300;
+
+// Parts of a frame that get timed, on the main thread unless noted.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Phase {
+    FixedUpdate,
+    // Picking which chunks to generate and handing them to the workers.
+    Queueing,
+    // Applying and lighting chunks the workers have generated.
+    Lighting,
+    // Snapshotting chunks for the workers to mesh.
+    Snapshots,
+    // Copying finished meshes into the arena.
+    Uploads,
+    // Picking far off regions and handing them to the workers.
+    Lod,
+    // Picking which chunks to draw.
+    Culling,
+    Render,
+    // Generating chunks and far off regions on the workers, see record_worker.
+    Generation,
+    // Meshing chunks and far off regions on the workers.
+    Meshing,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 10] = [
+        Phase::FixedUpdate, Phase::Queueing, Phase::Lighting, Phase::Snapshots, Phase::Uploads, Phase::Lod, Phase::Culling, Phase::Render,
+        Phase::Generation, Phase::Meshing,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::FixedUpdate => "fixed update",
+            Phase::Queueing => "queueing",
+            Phase::Lighting => "lighting",
+            Phase::Snapshots => "snapshots",
+            Phase::Uploads => "uploads",
+            Phase::Lod => "lod",
+            Phase::Culling => "culling",
+            Phase::Render => "render",
+            Phase::Generation => "generation",
+            Phase::Meshing => "meshing",
+        }
+    }
+
+    fn index(&self) -> usize {
+        Phase::ALL.iter().position(|phase| phase == self).unwrap()
+    }
+}
+
+// One timed phase, in microseconds since the profiler started.
+struct Span {
+    phase: Phase,
+    // 0 for the main thread, workers are numbered from 1.
+    thread: usize,
+    start: f64,
+    duration: f64,
+}
+
+// Everything timed in one frame.
+struct Frame {
+    // Microseconds since the profiler started.
+    start: f64,
+    // In milliseconds, the whole frame and then each phase summed up, in the order of Phase::ALL.
+    // Work on the workers is summed over all of them, so the phases can add up to more than the frame.
+    total: f64,
+    phases: [f64; Phase::ALL.len()],
+    spans: Vec<Span>,
+}
+
+// Average, median, 95th and 99th percentile and worst, in milliseconds.
+#[derive(Copy, Clone, Debug)]
+pub struct Stats {
+    pub average: f64,
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+// Keeps the timings of the last few hundred frames. There's one per thread, though only the main thread's is ever looked at.
+pub struct Profiler {
+    started: Instant,
+    frame_start: Instant,
+    current: Vec<Span>,
+    frames: VecDeque<Frame>,
+}
+
+thread_local! {
+    static PROFILER: RefCell<Profiler> = RefCell::new(Profiler::new());
+}
+
+// Times a phase until it's dropped. A phase timed more than once in a frame is summed up.
+pub struct Scope {
+    phase: Phase,
+    start: Instant,
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let (phase, start) = (self.phase, self.start);
+        PROFILER.with(|profiler| profiler.borrow_mut().record(0, phase, start, start.elapsed()));
+    }
+}
+
+pub fn scope(phase: Phase) -> Scope {
+    Scope { phase, start: Instant::now() }
+}
+
+// Records a phase a worker timed and sent back, as part of this thread's current frame.
+// It's counted in whichever frame it's received in, not the one it started in.
+pub fn record_worker(worker: usize, phase: Phase, start: Instant, duration: Duration) {
+    PROFILER.with(|profiler| profiler.borrow_mut().record(worker, phase, start, duration));
+}
+
+// Closes off the frame, everything timed since the last call being part of it.
+pub fn end_frame() {
+    PROFILER.with(|profiler| profiler.borrow_mut().end_frame());
+}
+
+pub fn with<T, F: FnOnce(&Profiler) -> T>(f: F) -> T {
+    PROFILER.with(|profiler| f(&profiler.borrow()))
+}
+
+impl Stats {
+
+    fn from_samples(mut samples: Vec<f64>) -> Stats {
+
+        if samples.is_empty() {
+            return Stats { average: 0.0, p50: 0.0, p95: 0.0, p99: 0.0, max: 0.0 };
+        }
+
+        samples.sort_by(|a, b| a.total_cmp(b));
+        let percentile = |p: f64| samples[((samples.len() - 1) as f64 * p).round() as usize];
+
+        Stats {
+            average: samples.iter().sum::<f64>() / samples.len() as f64,
+            p50: percentile(0.5),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+            max: samples[samples.len() - 1],
+        }
+    }
+}
+
+impl Profiler {
+
+    pub fn new() -> Profiler {
+        let now = Instant::now();
+        Profiler { started: now, frame_start: now, current: Vec::new(), frames: VecDeque::new() }
+    }
+
+    fn micros_since_start(&self, time: Instant) -> f64 {
+        time.duration_since(self.started).as_secs_f64() * 1_000_000.0
+    }
+
+    fn record(&mut self, thread: usize, phase: Phase, start: Instant, duration: Duration) {
+        let start = self.micros_since_start(start);
+        self.current.push(Span { phase, thread, start, duration: duration.as_secs_f64() * 1_000_000.0 });
+    }
+
+    fn end_frame(&mut self) {
+
+        let now = Instant::now();
+        let mut phases = [0.0; Phase::ALL.len()];
+        for span in self.current.iter() {
+            phases[span.phase.index()] += span.duration / 1000.0;
+        }
+
+        self.frames.push_back(Frame {
+            start: self.micros_since_start(self.frame_start),
+            total: now.duration_since(self.frame_start).as_secs_f64() * 1000.0,
+            phases,
+            spans: std::mem::take(&mut self.current),
+        });
+        if self.frames.len() > HISTORY {
+            self.frames.pop_front();
+        }
+
+        self.frame_start = now;
+    }
+
+    pub fn frame_stats(&self) -> Stats {
+        Stats::from_samples(self.frames.iter().map(|frame| frame.total).collect())
+    }
+
+    pub fn phase_stats(&self, phase: Phase) -> Stats {
+        Stats::from_samples(self.frames.iter().map(|frame| frame.phases[phase.index()]).collect())
+    }
+
+    // A line short enough for the window title, the frame time and the average of every phase.
+    pub fn summary(&self) -> String {
+
+        let frame = self.frame_stats();
+        let fps = if frame.average > 0.0 { 1000.0 / frame.average } else { 0.0 };
+        let mut summary = format!("{:.0} fps, frame {:.2}ms (p95 {:.2}, p99 {:.2})", fps, frame.average, frame.p95, frame.p99);
+
+        for phase in Phase::ALL.iter() {
+            summary.push_str(&format!(" | {} {:.2}", phase.name(), self.phase_stats(*phase).average));
+        }
+        summary
+    }
+
+    // Every stat of the frame and each phase, a line each.
+    pub fn report(&self) -> String {
+
+        let line = |name: &str, stats: Stats| format!("{:>12}: avg {:.2}ms, p50 {:.2}, p95 {:.2}, p99 {:.2}, max {:.2}\n", name, stats.average, stats.p50, stats.p95, stats.p99, stats.max);
+
+        let mut report = format!("Frame timings over the last {} frames:\n", self.frames.len());
+        report.push_str(&line("frame", self.frame_stats()));
+        for phase in Phase::ALL.iter() {
+            report.push_str(&line(phase.name(), self.phase_stats(*phase)));
+        }
+        report
+    }
+
+    // Every frame kept, one per row, with the time of each phase in milliseconds.
+    pub fn export_csv(&self, path: &str) -> std::io::Result<()> {
+
+        let mut csv = String::from("frame,start_ms,total_ms");
+        for phase in Phase::ALL.iter() {
+            csv.push_str(&format!(",{}_ms", phase.name().replace(' ', "_")));
+        }
+        csv.push('\n');
+
+        for (i, frame) in self.frames.iter().enumerate() {
+            csv.push_str(&format!("{},{:.3},{:.3}", i, frame.start / 1000.0, frame.total));
+            for time in frame.phases.iter() {
+                csv.push_str(&format!(",{:.3}", time));
+            }
+            csv.push('\n');
+        }
+
+        fs::write(path, csv)
+    }
+
+    // Every phase timed in the frames kept, in the Chrome trace event format,
+    // which chrome://tracing and Perfetto can open. Each worker gets a track of its own below the main thread.
+    pub fn export_trace(&self, path: &str) -> std::io::Result<()> {
+
+        let mut events = Vec::new();
+        for frame in self.frames.iter() {
+            events.push(format!(r#"{{"name":"frame","ph":"X","pid":1,"tid":1,"ts":{:.3},"dur":{:.3}}}"#, frame.start, frame.total * 1000.0));
+            for span in frame.spans.iter() {
+                events.push(format!(r#"{{"name":"{}","ph":"X","pid":1,"tid":{},"ts":{:.3},"dur":{:.3}}}"#, span.phase.name(), span.thread + 1, span.start, span.duration));
+            }
+        }
+
+        fs::write(path, format!("{{\"traceEvents\":[\n{}\n]}}\n", events.join(",\n")))
+    }
+}
@@ -4,13 +4,20 @@ pub mod texture;
 pub mod vertex;
 pub mod mesh;
 pub mod ui;
-
-use mesh::Mesh;
-use vertex::Vertex;
+pub mod shadow;
+pub mod sky;
+pub mod clouds;
+pub mod precipitation;
+pub mod arena;
+
+use arena::{ChunkArena, ChunkMesh};
+use mesh::QuadIndexBuffer;
+use vertex::{ChunkInstance, ChunkVertex};
 use super::color::Color;
 
 pub struct Renderer {
     pub default_pipeline: wgpu::RenderPipeline,
+    pub translucent_pipeline: wgpu::RenderPipeline,
     pub ui_manager: ui::UIManager,
     pub swap_chain_desc: wgpu::SwapChainDescriptor,
     pub swap_chain: wgpu::SwapChain,
@@ -20,6 +27,12 @@ pub struct Renderer {
     pub default_uniforms: uniforms::DefaultUniforms,
     pub depth_texture: texture::Texture,
     pub block_atlas: texture::Texture,
+    pub shadow_map: shadow::ShadowMap,
+    pub sky: sky::Sky,
+    pub clouds: clouds::Clouds,
+    pub precipitation: precipitation::Precipitation,
+    pub quad_indices: QuadIndexBuffer,
+    pub chunk_arena: ChunkArena,
 }
 
 pub const WIREFRAME_MODE: bool = false;
@@ -36,6 +49,17 @@ impl Renderer {
             compatible_surface: Some(&surface),
         }).await.expect("Failed to create adapter.");
 
+        // Chunks are drawn with a single call per pass where that's supported.
+        let multi_draw = adapter.features().contains(wgpu::Features::MULTI_DRAW_INDIRECT);
+        let mut features = if WIREFRAME_MODE { wgpu::Features::NON_FILL_POLYGON_MODE } else { wgpu::Features::empty() };
+        if multi_draw {
+            features |= wgpu::Features::MULTI_DRAW_INDIRECT;
+        }
+
+        // Chunk draws find their origin by starting at their own instance, which indirect draws can't do everywhere.
+        // wgpu doesn't report or turn on Vulkan's drawIndirectFirstInstance, so there they're drawn directly instead.
+        let indirect_first_instance = matches!(adapter.get_info().backend, wgpu::Backend::Metal | wgpu::Backend::Dx12);
+
         // Creates a device and a command queue.
         // The device is a connection to the GPU, and the command queue
         // is the list of commands for the GPU to perform.
@@ -43,7 +67,7 @@ impl Renderer {
             &wgpu::DeviceDescriptor 
             {
                 label: None,
-                features: if WIREFRAME_MODE { wgpu::Features::NON_FILL_POLYGON_MODE } else { wgpu::Features::empty() },
+                features,
                 limits: wgpu::Limits::default(),
             },
             None
@@ -95,31 +119,66 @@ impl Renderer {
 
         let default_uniforms = uniforms::DefaultUniforms::new(&device);
         let ui_manager = ui::UIManager::new(&device, &swap_chain_desc, &queue);
+        let shadow_map = shadow::ShadowMap::new(&device, &texture_bind_group_layout);
+        let sky = sky::Sky::new(&device, swap_chain_desc.format);
+        let clouds = clouds::Clouds::new(&device, swap_chain_desc.format);
+        let precipitation = precipitation::Precipitation::new(&device, swap_chain_desc.format);
+        // As many quads as u16 indices can reach, it only grows past that if a mesh needs it to.
+        let quad_indices = QuadIndexBuffer::new(&device, (u16::MAX as usize + 1) / 4);
+        let chunk_arena = ChunkArena::new(&device, 1 << 20, multi_draw, indirect_first_instance);
 
         let default_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
-            bind_group_layouts: &[&default_uniforms.bind_group_layout, &texture_bind_group_layout],
+            bind_group_layouts: &[&default_uniforms.bind_group_layout, &texture_bind_group_layout, &shadow_map.bind_group_layout],
             push_constant_ranges: &[],
         });
 
 
-        let default_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        let default_pipeline = Renderer::create_chunk_pipeline(&device, &default_pipeline_layout, &default_shader, swap_chain_desc.format, false);
+        let translucent_pipeline = Renderer::create_chunk_pipeline(&device, &default_pipeline_layout, &default_shader, swap_chain_desc.format, true);
+
+        Renderer {
+            default_pipeline,
+            translucent_pipeline,
+            ui_manager,
+            swap_chain_desc,
+            swap_chain,
+            device,
+            queue,
+            surface,
+            default_uniforms,
+            depth_texture,
+            block_atlas,
+            shadow_map,
+            sky,
+            clouds,
+            precipitation,
+            quad_indices,
+            chunk_arena,
+        }
+    }
+
+    // Opaque and cutout chunks share a pipeline, as cutout is done by discarding fragments.
+    // Translucent chunks are alpha blended and don't write to the depth buffer.
+    fn create_chunk_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, shader: &wgpu::ShaderModule, format: wgpu::TextureFormat, translucent: bool) -> wgpu::RenderPipeline {
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: None,
-            layout: Some(&default_pipeline_layout),
+            layout: Some(layout),
 
             vertex: wgpu::VertexState {
-                module: &default_shader,
+                module: shader,
                 entry_point: "vertex",
-                buffers: &[Vertex::layout()],
+                buffers: &[ChunkVertex::layout(), ChunkInstance::layout()],
             },
 
             fragment: Some(wgpu::FragmentState {
-                module: &default_shader,
-                entry_point: "fragment",
+                module: shader,
+                entry_point: if translucent { "fragment_translucent" } else { "fragment" },
                 targets: &[
                     wgpu::ColorTargetState {
-                        format: swap_chain_desc.format,
-                        blend: Some(wgpu::BlendState::REPLACE),
+                        format,
+                        blend: Some(if translucent { wgpu::BlendState::ALPHA_BLENDING } else { wgpu::BlendState::REPLACE }),
                         write_mask: wgpu::ColorWrite::ALL,
                     },
                 ],
@@ -129,7 +188,8 @@ impl Renderer {
                 topology: wgpu::PrimitiveTopology::TriangleList,
                 strip_index_format: None,
                 front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
+                // Translucent surfaces like water can be seen from both sides.
+                cull_mode: if translucent { None } else { Some(wgpu::Face::Back) },
                 clamp_depth: false,
                 polygon_mode: if WIREFRAME_MODE { wgpu::PolygonMode::Line} else { wgpu::PolygonMode::Fill }, 
                 conservative: false,
@@ -138,33 +198,42 @@ impl Renderer {
 
             depth_stencil: Some(wgpu::DepthStencilState {
                 format: texture::Texture::DEPTH_FORMAT,
-                depth_write_enabled: true,
+                depth_write_enabled: !translucent,
                 depth_compare: wgpu::CompareFunction::Less,
                 stencil: wgpu::StencilState::default(),
                 bias: wgpu::DepthBiasState::default(),
             }),
 
-        });
-
-        Renderer {
-            default_pipeline,
-            ui_manager,
-            swap_chain_desc,
-            swap_chain,
-            device,
-            queue,
-            surface,
-            default_uniforms,
-            depth_texture,
-            block_atlas,
-        }
+        })
     }
 
-    pub fn render(&mut self, camera: &super::camera::Camera, pool: &Vec<&Mesh>, sky_color: Color) {
+    // 'shadow_pool' is every opaque mesh that could cast a shadow into view, frustum culled or not.
+    pub fn render(&mut self, camera: &super::camera::Camera, pool: &Vec<&ChunkMesh>, translucent_pool: &Vec<&ChunkMesh>, shadow_pool: &Vec<&ChunkMesh>, sky_color: Color) {
+
+        let most_quads = pool.iter().chain(translucent_pool.iter()).chain(shadow_pool.iter()).map(|mesh| mesh.quad_count as usize).max().unwrap_or(0);
+        self.quad_indices.reserve(&self.device, most_quads);
+
+        let draws = self.chunk_arena.prepare(&self.device, &self.queue, &[shadow_pool, pool, translucent_pool]);
+        let (shadow_draws, draws, translucent_draws) = (draws[0].clone(), draws[1].clone(), draws[2].clone());
+
+        let light_view_proj = self.shadow_map.build_light_view_projection_matrix(camera);
+        self.shadow_map.data.light_view_proj = light_view_proj.into();
+        self.shadow_map.write(&self.queue);
 
         self.default_uniforms.update_view_proj(camera.build_view_projection_matrix());
+        self.default_uniforms.data.light_view_proj = light_view_proj.into();
         self.default_uniforms.write(&self.queue);
 
+        self.sky.update_camera(camera);
+        self.sky.write(&self.queue);
+
+        self.clouds.data.view_proj = camera.build_view_projection_matrix().into();
+        self.clouds.data.camera_position = camera.position.into();
+        self.clouds.write(&self.queue);
+
+        self.precipitation.data.view_proj = camera.build_view_projection_matrix().into();
+        self.precipitation.write(&self.queue);
+
         self.ui_manager.uniforms.update_view_proj(camera.build_ui_projection_matrix());
         self.ui_manager.uniforms.write(&self.queue);
 
@@ -177,6 +246,34 @@ impl Renderer {
             label: None,
         });
 
+        // Shadow Render Pass
+        {
+            let mut shadow_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.shadow_map.texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+
+            shadow_pass.set_pipeline(&self.shadow_map.pipeline);
+            shadow_pass.set_bind_group(0, &self.shadow_map.uniform_bind_group, &[]);
+
+            match &self.block_atlas.bind_group {
+                Some(bg) => {
+                    shadow_pass.set_bind_group(1, bg, &[]);
+                },
+                None => ()
+            }
+
+            self.chunk_arena.draw(&mut shadow_pass, &self.quad_indices, shadow_draws);
+        }
+
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: None,
@@ -200,7 +297,11 @@ impl Renderer {
                 }),
             });
 
-            
+            // Sky Render Pass, a single fullscreen triangle behind everything else.
+            render_pass.set_pipeline(&self.sky.pipeline);
+            render_pass.set_bind_group(0, &self.sky.bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+
             render_pass.set_pipeline(&self.default_pipeline);
             render_pass.set_bind_group(0, &self.default_uniforms.bind_group, &[]);
 
@@ -212,11 +313,37 @@ impl Renderer {
                 },
                 None => ()
             }
+            match &self.shadow_map.texture.bind_group {
+                Some(bg) => {
+                    render_pass.set_bind_group(2, bg, &[]);
+                },
+                None => ()
+            }
+
+            self.chunk_arena.draw(&mut render_pass, &self.quad_indices, draws);
+
+            // Clouds are drawn around the camera wherever it is, so they aren't frustum culled.
+            if let Some(mesh) = &self.clouds.mesh {
+                render_pass.set_bind_group(0, &self.clouds.bind_group, &[]);
+
+                render_pass.set_pipeline(&self.clouds.depth_pipeline);
+                mesh.draw(&mut render_pass, 0..9);
+                render_pass.set_pipeline(&self.clouds.pipeline);
+                mesh.draw(&mut render_pass, 0..9);
+
+                render_pass.set_bind_group(0, &self.default_uniforms.bind_group, &[]);
+            }
+
+            // Translucent chunks, expected to be sorted back to front.
+            render_pass.set_pipeline(&self.translucent_pipeline);
+
+            self.chunk_arena.draw(&mut render_pass, &self.quad_indices, translucent_draws);
 
-            for mesh in pool {
-                render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-                render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-                render_pass.draw_indexed(0..mesh.indices.len() as u32, 0, 0..1);
+            // Rain and snow, blended over everything in the world.
+            if self.precipitation.particle_count > 0 {
+                render_pass.set_pipeline(&self.precipitation.pipeline);
+                render_pass.set_bind_group(0, &self.precipitation.bind_group, &[]);
+                render_pass.draw(0..6, 0..self.precipitation.particle_count);
             }
 
             // UI Render Pass
@@ -234,9 +361,7 @@ impl Renderer {
                             None => ()
                         }
 
-                        render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-                        render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-                        render_pass.draw_indexed(0..mesh.indices.len() as u32, 0, 0..1);
+                        mesh.draw(&mut render_pass, 0..1);
                     },
                     None => ()
                 }
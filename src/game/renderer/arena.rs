@@ -0,0 +1,247 @@
+use std::{ops::Range, sync::{Arc, Mutex}};
+
+use super::{mesh::QuadIndexBuffer, vertex::{ChunkInstance, ChunkVertex}};
+
+// The arguments of one indexed indirect draw, laid out the way the GPU reads them.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct DrawIndexedIndirect {
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+// Free ranges of the arena as (first vertex, vertex count), ordered and never touching each other.
+struct FreeList {
+    ranges: Vec<(u32, u32)>,
+}
+
+impl FreeList {
+
+    // Takes the first range big enough.
+    fn allocate(&mut self, count: u32) -> Option<u32> {
+        let i = self.ranges.iter().position(|(_, size)| *size >= count)?;
+        let (start, size) = self.ranges[i];
+        if size == count {
+            self.ranges.remove(i);
+        }
+        else {
+            self.ranges[i] = (start + count, size - count);
+        }
+        Some(start)
+    }
+
+    // Gives a range back, merging it with the free ranges on either side.
+    fn free(&mut self, start: u32, count: u32) {
+        if count == 0 { return; }
+
+        let i = self.ranges.iter().position(|(s, _)| *s > start).unwrap_or(self.ranges.len());
+        self.ranges.insert(i, (start, count));
+
+        if i + 1 < self.ranges.len() && start + count == self.ranges[i + 1].0 {
+            self.ranges[i].1 += self.ranges[i + 1].1;
+            self.ranges.remove(i + 1);
+        }
+        if i > 0 && self.ranges[i - 1].0 + self.ranges[i - 1].1 == start {
+            self.ranges[i - 1].1 += self.ranges[i].1;
+            self.ranges.remove(i);
+        }
+    }
+}
+
+// Vertices of one chunk mesh in the arena. They're freed again when this is dropped,
+// so a chunk gives back its space just by having its mesh replaced or being unloaded.
+pub struct ArenaAllocation {
+    pub first_vertex: u32,
+    pub vertex_count: u32,
+    free_list: Arc<Mutex<FreeList>>,
+}
+
+impl Drop for ArenaAllocation {
+    fn drop(&mut self) {
+        self.free_list.lock().unwrap().free(self.first_vertex, self.vertex_count);
+    }
+}
+
+pub struct ChunkMesh {
+    pub allocation: ArenaAllocation,
+    pub quad_count: u32,
+    pub instance: ChunkInstance,
+}
+
+// Every chunk mesh shares one big vertex buffer, handed out in ranges.
+// Chunks are drawn from an indirect buffer built every frame, each draw reading its chunk's origin as instance data,
+// so nothing gets rebound between chunks. Where indirect draws can't start past the first instance,
+// the same draws are issued directly instead.
+pub struct ChunkArena {
+    pub vertex_buffer: wgpu::Buffer,
+    // In vertices.
+    pub capacity: u32,
+    free_list: Arc<Mutex<FreeList>>,
+
+    pub instance_buffer: wgpu::Buffer,
+    pub indirect_buffer: wgpu::Buffer,
+    // In draws, both buffers hold this many.
+    pub draw_capacity: u32,
+
+    // Whether a whole list of draws can be issued at once, otherwise they're issued one at a time.
+    pub multi_draw: bool,
+    // Whether indirect draws can start at an instance other than the first, see Renderer::new.
+    pub indirect_first_instance: bool,
+    // What was last written to the indirect buffer, for drawing directly.
+    draws: Vec<DrawIndexedIndirect>,
+}
+
+impl ChunkArena {
+
+    pub fn new(device: &wgpu::Device, capacity: u32, multi_draw: bool, indirect_first_instance: bool) -> ChunkArena {
+
+        let draw_capacity = 1024;
+        let (instance_buffer, indirect_buffer) = ChunkArena::create_draw_buffers(device, draw_capacity);
+
+        ChunkArena {
+            vertex_buffer: ChunkArena::create_vertex_buffer(device, capacity),
+            capacity,
+            free_list: Arc::new(Mutex::new(FreeList { ranges: vec![(0, capacity)] })),
+
+            instance_buffer,
+            indirect_buffer,
+            draw_capacity,
+
+            multi_draw,
+            indirect_first_instance,
+            draws: Vec::new(),
+        }
+    }
+
+    fn create_vertex_buffer(device: &wgpu::Device, capacity: u32) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("chunk arena"),
+            size: capacity as u64 * std::mem::size_of::<ChunkVertex>() as u64,
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::COPY_SRC,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_draw_buffers(device: &wgpu::Device, draw_capacity: u32) -> (wgpu::Buffer, wgpu::Buffer) {
+
+        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: draw_capacity as u64 * std::mem::size_of::<ChunkInstance>() as u64,
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let indirect_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: draw_capacity as u64 * std::mem::size_of::<DrawIndexedIndirect>() as u64,
+            usage: wgpu::BufferUsage::INDIRECT | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        (instance_buffer, indirect_buffer)
+    }
+
+    // Copies a chunk's vertices into the arena, growing it if there's no room left.
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, vertices: &[ChunkVertex], instance: ChunkInstance) -> ChunkMesh {
+
+        let count = vertices.len() as u32;
+        let first_vertex = loop {
+            let first = self.free_list.lock().unwrap().allocate(count);
+            match first {
+                Some(first) => break first,
+                None => self.grow(device, queue, count),
+            }
+        };
+
+        let offset = first_vertex as u64 * std::mem::size_of::<ChunkVertex>() as u64;
+        queue.write_buffer(&self.vertex_buffer, offset, bytemuck::cast_slice(vertices));
+
+        ChunkMesh {
+            allocation: ArenaAllocation { first_vertex, vertex_count: count, free_list: self.free_list.clone() },
+            quad_count: count / 4,
+            instance,
+        }
+    }
+
+    // At least doubles the arena, copying everything over so existing allocations stay where they are.
+    fn grow(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, needed: u32) {
+
+        let capacity = (self.capacity + needed).next_power_of_two().max(self.capacity * 2);
+        let vertex_buffer = ChunkArena::create_vertex_buffer(device, capacity);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_buffer_to_buffer(&self.vertex_buffer, 0, &vertex_buffer, 0, self.capacity as u64 * std::mem::size_of::<ChunkVertex>() as u64);
+        queue.submit(Some(encoder.finish()));
+
+        self.free_list.lock().unwrap().free(self.capacity, capacity - self.capacity);
+        self.vertex_buffer = vertex_buffer;
+        self.capacity = capacity;
+    }
+
+    // Writes out the draws for every list of meshes, one after another, and returns which draws belong to which list.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, lists: &[&Vec<&ChunkMesh>]) -> Vec<Range<u32>> {
+
+        let mut instances = Vec::new();
+        let mut draws = Vec::new();
+        let mut ranges = Vec::new();
+
+        for list in lists {
+            let start = draws.len() as u32;
+            for mesh in list.iter() {
+                draws.push(DrawIndexedIndirect {
+                    index_count: mesh.quad_count * 6,
+                    instance_count: 1,
+                    first_index: 0,
+                    base_vertex: mesh.allocation.first_vertex as i32,
+                    first_instance: instances.len() as u32,
+                });
+                instances.push(mesh.instance);
+            }
+            ranges.push(start..draws.len() as u32);
+        }
+
+        if draws.len() as u32 > self.draw_capacity {
+            self.draw_capacity = (draws.len() as u32).next_power_of_two();
+            let (instance_buffer, indirect_buffer) = ChunkArena::create_draw_buffers(device, self.draw_capacity);
+            self.instance_buffer = instance_buffer;
+            self.indirect_buffer = indirect_buffer;
+        }
+
+        if !draws.is_empty() {
+            queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instances));
+            queue.write_buffer(&self.indirect_buffer, 0, bytemuck::cast_slice(&draws));
+        }
+
+        self.draws = draws;
+        ranges
+    }
+
+    // Draws a range of what was prepared with the current pipeline.
+    pub fn draw<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, quad_indices: &'a QuadIndexBuffer, draws: Range<u32>) {
+
+        if draws.is_empty() { return; }
+
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        pass.set_index_buffer(quad_indices.buffer.slice(..), quad_indices.format);
+
+        let stride = std::mem::size_of::<DrawIndexedIndirect>() as u64;
+        if !self.indirect_first_instance {
+            for draw in &self.draws[draws.start as usize..draws.end as usize] {
+                let indices = draw.first_index..draw.first_index + draw.index_count;
+                pass.draw_indexed(indices, draw.base_vertex, draw.first_instance..draw.first_instance + draw.instance_count);
+            }
+        }
+        else if self.multi_draw {
+            pass.multi_draw_indexed_indirect(&self.indirect_buffer, draws.start as u64 * stride, draws.end - draws.start);
+        }
+        else {
+            for draw in draws {
+                pass.draw_indexed_indirect(&self.indirect_buffer, draw as u64 * stride);
+            }
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use wgpu::{Queue, util::DeviceExt};
+use cgmath::{InnerSpace, Matrix4, Vector3};
+
+use super::{mesh::Mesh, texture, vertex::Vertex};
+
+// Width of the cloud map in cells, it tiles on both axes.
+pub const CLOUD_MAP_SIZE: usize = 48;
+
+// Size of a single cloud cell, in blocks.
+pub const CLOUD_CELL_SIZE: f32 = 12.0;
+pub const CLOUD_THICKNESS: f32 = 4.0;
+
+// How far from the camera clouds start and finish fading out, in blocks.
+pub const CLOUD_FADE_START: f32 = 200.0;
+pub const CLOUD_FADE_END: f32 = 500.0;
+
+// IMPORANT!! wgpu requires that uniforms are spaced by 16 bytes!
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CloudData {
+    pub view_proj: [[f32; 4]; 4],
+
+    pub camera_position: [f32; 3],
+    pub tile_size: f32,
+    // World position of the tile the camera is over, including drift.
+    pub offset: [f32; 3],
+    pub fade_start: f32,
+    pub light_color: [f32; 3],
+    pub fade_end: f32,
+}
+
+// A layer of blocky clouds, built once from the world's cloud map and drawn every frame.
+// It's drawn twice, first only to the depth buffer, so only the nearest cloud faces are blended
+// and overlapping clouds don't stack up their transparency.
+pub struct Clouds {
+    pub depth_pipeline: wgpu::RenderPipeline,
+    pub pipeline: wgpu::RenderPipeline,
+    pub mesh: Option<Mesh>,
+
+    pub data: CloudData,
+    pub buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl Clouds {
+
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Clouds {
+        use cgmath::SquareMatrix;
+
+        let data = CloudData {
+            view_proj: Matrix4::identity().into(),
+
+            camera_position: [0.0, 0.0, 0.0],
+            tile_size: CLOUD_MAP_SIZE as f32 * CLOUD_CELL_SIZE,
+            offset: [0.0, 0.0, 0.0],
+            fade_start: CLOUD_FADE_START,
+            light_color: [1.0, 1.0, 1.0],
+            fade_end: CLOUD_FADE_END,
+        };
+
+        let buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(&[data]),
+                usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            }
+        );
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }
+            ]
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                }
+            ],
+            label: None,
+        });
+
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/clouds.wgsl").into()),
+            flags: wgpu::ShaderFlags::empty(),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let depth_pipeline = Clouds::create_pipeline(device, &pipeline_layout, &shader, format, true);
+        let pipeline = Clouds::create_pipeline(device, &pipeline_layout, &shader, format, false);
+
+        Clouds { depth_pipeline, pipeline, mesh: None, data, buffer, bind_group }
+    }
+
+    // The depth only pipeline writes no color, the other blends color onto the depth it left behind.
+    fn create_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, shader: &wgpu::ShaderModule, format: wgpu::TextureFormat, depth_only: bool) -> wgpu::RenderPipeline {
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(layout),
+
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vertex",
+                buffers: &[Vertex::layout()],
+            },
+
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fragment",
+                targets: &[
+                    wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: if depth_only { wgpu::ColorWrite::empty() } else { wgpu::ColorWrite::ALL },
+                    },
+                ],
+            }),
+
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                clamp_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            multisample: wgpu::MultisampleState::default(),
+
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: depth_only,
+                depth_compare: if depth_only { wgpu::CompareFunction::Less } else { wgpu::CompareFunction::LessEqual },
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+        })
+    }
+
+    // Builds boxes for every filled cell of the cloud map, leaving out sides shared between two cells.
+    // The map wraps around, so sides on its edges are checked against the opposite edge.
+    pub fn build_mesh(&mut self, device: &wgpu::Device, map: &[bool]) {
+
+        let size = CLOUD_MAP_SIZE as i32;
+        let filled = |x: i32, z: i32| map[(x.rem_euclid(size) * size + z.rem_euclid(size)) as usize];
+
+        let mut vertices: Vec<Vertex> = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+
+        for x in 0..size {
+            for z in 0..size {
+
+                if !filled(x, z) { continue; }
+
+                let min = Vector3::new(x as f32 * CLOUD_CELL_SIZE, 0.0, z as f32 * CLOUD_CELL_SIZE);
+                let max = min + Vector3::new(CLOUD_CELL_SIZE, CLOUD_THICKNESS, CLOUD_CELL_SIZE);
+
+                let corner = |cx: bool, cy: bool, cz: bool| Vector3::new(
+                    if cx { max.x } else { min.x },
+                    if cy { max.y } else { min.y },
+                    if cz { max.z } else { min.z },
+                );
+
+                Clouds::push_face(&mut vertices, &mut indices, Vector3::unit_y(), [corner(false, true, false), corner(false, true, true), corner(true, true, true), corner(true, true, false)]);
+                Clouds::push_face(&mut vertices, &mut indices, -Vector3::unit_y(), [corner(false, false, false), corner(true, false, false), corner(true, false, true), corner(false, false, true)]);
+
+                if !filled(x - 1, z) {
+                    Clouds::push_face(&mut vertices, &mut indices, -Vector3::unit_x(), [corner(false, false, false), corner(false, false, true), corner(false, true, true), corner(false, true, false)]);
+                }
+                if !filled(x + 1, z) {
+                    Clouds::push_face(&mut vertices, &mut indices, Vector3::unit_x(), [corner(true, false, false), corner(true, true, false), corner(true, true, true), corner(true, false, true)]);
+                }
+                if !filled(x, z - 1) {
+                    Clouds::push_face(&mut vertices, &mut indices, -Vector3::unit_z(), [corner(false, false, false), corner(false, true, false), corner(true, true, false), corner(true, false, false)]);
+                }
+                if !filled(x, z + 1) {
+                    Clouds::push_face(&mut vertices, &mut indices, Vector3::unit_z(), [corner(false, false, true), corner(true, false, true), corner(true, true, true), corner(false, true, true)]);
+                }
+            }
+        }
+
+        self.mesh = Some(Mesh::new(device, vertices, indices));
+    }
+
+    // Corners are flipped if needed so the face winds counter clockwise seen from outside.
+    fn push_face(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, normal: Vector3<f32>, mut corners: [Vector3<f32>; 4]) {
+
+        if (corners[1] - corners[0]).cross(corners[2] - corners[0]).dot(normal) < 0.0 {
+            corners.reverse();
+        }
+
+        for corner in corners.iter() {
+            vertices.push(Vertex {
+                position: (*corner).into(),
+                normal: normal.into(),
+                tex_coords: [0.0, 0.0],
+            });
+        }
+
+        let l = vertices.len() as u32;
+        indices.extend_from_slice(&[l - 4, l - 3, l - 2, l - 4, l - 2, l - 1]);
+    }
+
+    pub fn write(&self, queue: &Queue) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.data]));
+    }
+}
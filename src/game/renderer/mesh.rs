@@ -1,18 +1,30 @@
+use std::ops::Range;
+
 use wgpu::util::DeviceExt;
 
 use super::vertex::Vertex;
 
-pub struct Mesh {
-    pub vertices: Vec<Vertex>,
-    pub indices: Vec<u16>,
+// The indices as bytes, as u16s if every one of 'vertex_count' vertices can be reached with them, otherwise as u32s.
+fn index_bytes(indices: &[u32], vertex_count: usize) -> (Vec<u8>, wgpu::IndexFormat) {
+    if vertex_count <= u16::MAX as usize + 1 {
+        let short: Vec<u16> = indices.iter().map(|i| *i as u16).collect();
+        (bytemuck::cast_slice(&short).to_vec(), wgpu::IndexFormat::Uint16)
+    }
+    else {
+        (bytemuck::cast_slice(indices).to_vec(), wgpu::IndexFormat::Uint32)
+    }
+}
 
+pub struct Mesh {
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
+    pub index_format: wgpu::IndexFormat,
+    pub index_count: u32,
 }
 
 impl Mesh {
 
-    pub fn new(device: &wgpu::Device, vertices: Vec<Vertex>, indices: Vec<u16>) -> Mesh {
+    pub fn new(device: &wgpu::Device, vertices: Vec<Vertex>, indices: Vec<u32>) -> Mesh {
 
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
@@ -20,20 +32,64 @@ impl Mesh {
             usage: wgpu::BufferUsage::VERTEX,
         });
 
+        let (contents, index_format) = index_bytes(&indices, vertices.len());
+
         let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
-            contents: bytemuck::cast_slice(&indices),
+            contents: &contents,
             usage: wgpu::BufferUsage::INDEX,
         });
 
         Mesh {
-            vertices,
-            indices,
-
             vertex_buffer,
             index_buffer,
+            index_format,
+            index_count: indices.len() as u32,
         }
 
     }
 
-}
\ No newline at end of file
+    pub fn draw<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, instances: Range<u32>) {
+
+        pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        pass.set_index_buffer(self.index_buffer.slice(..), self.index_format);
+        pass.draw_indexed(0..self.index_count, 0, instances);
+    }
+
+}
+
+// Indices for drawing quads, the same for every chunk mesh, so they're only built once and shared.
+// Grows whenever a mesh has more quads than it covers, switching to u32s once u16s can't reach every vertex.
+pub struct QuadIndexBuffer {
+    pub buffer: wgpu::Buffer,
+    pub format: wgpu::IndexFormat,
+    pub quad_count: usize,
+}
+
+impl QuadIndexBuffer {
+
+    pub fn new(device: &wgpu::Device, quad_count: usize) -> QuadIndexBuffer {
+
+        let mut indices: Vec<u32> = Vec::with_capacity(quad_count * 6);
+        for quad in 0..quad_count as u32 {
+            let l = quad * 4;
+            indices.extend_from_slice(&[l, l + 1, l + 2, l, l + 2, l + 3]);
+        }
+
+        let (contents, format) = index_bytes(&indices, quad_count * 4);
+
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: &contents,
+            usage: wgpu::BufferUsage::INDEX,
+        });
+
+        QuadIndexBuffer { buffer, format, quad_count }
+    }
+
+    pub fn reserve(&mut self, device: &wgpu::Device, quad_count: usize) {
+        if quad_count > self.quad_count {
+            *self = QuadIndexBuffer::new(device, quad_count.next_power_of_two());
+        }
+    }
+}
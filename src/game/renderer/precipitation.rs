@@ -0,0 +1,228 @@
+use wgpu::{Queue, util::DeviceExt};
+use cgmath::Matrix4;
+
+use super::texture;
+
+// Most particles drawn at once, at full intensity.
+pub const PARTICLE_COUNT: u32 = 6000;
+
+// Width of the box around the camera particles are kept in, in blocks.
+pub const AREA_SIZE: f32 = 48.0;
+
+// Width of the area around the camera covered by the heightmap, in columns.
+pub const HEIGHTMAP_SIZE: u32 = 64;
+
+// Seconds between heightmap updates, so new roofs start blocking precipitation.
+pub const HEIGHTMAP_UPDATE_INTERVAL: f32 = 0.25;
+
+// IMPORANT!! wgpu requires that uniforms are spaced by 16 bytes!
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PrecipitationData {
+    pub view_proj: [[f32; 4]; 4],
+
+    pub camera_position: [f32; 3],
+    pub time: f32,
+    pub color: [f32; 3],
+    pub fall_speed: f32,
+    pub particle_size: [f32; 2],
+    pub sway: f32,
+    pub area_size: f32,
+    pub heightmap_origin: [i32; 2],
+    pub padding: [u32; 2],
+}
+
+// Rain and snow, as particles placed and animated entirely on the GPU.
+// Nothing is stored per particle, only a heightmap of the columns around the camera so
+// particles under a roof can be hidden.
+pub struct Precipitation {
+    pub pipeline: wgpu::RenderPipeline,
+    pub heightmap: wgpu::Texture,
+
+    pub data: PrecipitationData,
+    pub buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+
+    // How many particles to draw this frame.
+    pub particle_count: u32,
+    pub time_since_heightmap: f32,
+}
+
+impl Precipitation {
+
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Precipitation {
+        use cgmath::SquareMatrix;
+
+        let data = PrecipitationData {
+            view_proj: Matrix4::identity().into(),
+
+            camera_position: [0.0, 0.0, 0.0],
+            time: 0.0,
+            color: [1.0, 1.0, 1.0],
+            fall_speed: 0.0,
+            particle_size: [0.0, 0.0],
+            sway: 0.0,
+            area_size: AREA_SIZE,
+            heightmap_origin: [0, 0],
+            padding: [0, 0],
+        };
+
+        let buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(&[data]),
+                usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            }
+        );
+
+        let heightmap = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: HEIGHTMAP_SIZE,
+                height: HEIGHTMAP_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R32Float,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+        });
+        let heightmap_view = heightmap.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::VERTEX,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                }
+            ]
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&heightmap_view),
+                }
+            ],
+            label: None,
+        });
+
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/precipitation.wgsl").into()),
+            flags: wgpu::ShaderFlags::empty(),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        // Six vertices per particle, generated in the shader without any vertex buffer.
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vertex",
+                buffers: &[],
+            },
+
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fragment",
+                targets: &[
+                    wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrite::ALL,
+                    },
+                ],
+            }),
+
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                clamp_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            multisample: wgpu::MultisampleState::default(),
+
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+        });
+
+        Precipitation {
+            pipeline,
+            heightmap,
+            data,
+            buffer,
+            bind_group,
+            particle_count: 0,
+            time_since_heightmap: HEIGHTMAP_UPDATE_INTERVAL,
+        }
+    }
+
+    pub fn write(&self, queue: &Queue) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.data]));
+    }
+
+    // 'heights' holds HEIGHTMAP_SIZE columns along x for each z, starting at 'origin'.
+    pub fn write_heightmap(&mut self, queue: &Queue, origin: [i32; 2], heights: &[f32]) {
+
+        self.data.heightmap_origin = origin;
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.heightmap,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            bytemuck::cast_slice(heights),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(4 * HEIGHTMAP_SIZE),
+                rows_per_image: std::num::NonZeroU32::new(HEIGHTMAP_SIZE),
+            },
+            wgpu::Extent3d {
+                width: HEIGHTMAP_SIZE,
+                height: HEIGHTMAP_SIZE,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
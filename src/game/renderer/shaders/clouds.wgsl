@@ -0,0 +1,61 @@
+
+[[block]]
+struct Uniforms {
+    view_proj: mat4x4<f32>;
+
+    camera_position: vec3<f32>;
+    tile_size: f32;
+    offset: vec3<f32>;
+    fade_start: f32;
+    light_color: vec3<f32>;
+    fade_end: f32;
+};
+
+[[group(0), binding(0)]]
+var<uniform> uniforms: Uniforms;
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] normal: vec3<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] normal: vec3<f32>;
+    [[location(1)]] world_position: vec3<f32>;
+};
+
+// The cloud map is a single tile, drawn 3x3 times around the camera.
+[[stage(vertex)]]
+fn vertex(model: VertexInput, [[builtin(instance_index)]] instance: u32) -> VertexOutput {
+
+    let tile = vec3<f32>(f32(i32(instance % 3u) - 1), 0.0, f32(i32(instance / 3u) - 1)) * uniforms.tile_size;
+    let world_position = model.position + uniforms.offset + tile;
+
+    var out: VertexOutput;
+    out.clip_position = uniforms.view_proj * vec4<f32>(world_position, 1.0);
+    out.normal = model.normal;
+    out.world_position = world_position;
+    return out;
+
+}
+
+[[stage(fragment)]]
+fn fragment(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+
+    // Tops catch the most light, undersides the least.
+    var brightness: f32 = 0.8;
+    if (in.normal.y > 0.5) {
+        brightness = 1.0;
+    } elseif (in.normal.y < -0.5) {
+        brightness = 0.65;
+    }
+
+    let color = (vec3<f32>(0.15, 0.15, 0.15) + uniforms.light_color) * brightness;
+
+    // Clouds thin out with distance instead of ending at the edge of the tiles.
+    let offset = in.world_position.xz - uniforms.camera_position.xz;
+    let alpha = 0.8 * (1.0 - smoothStep(uniforms.fade_start, uniforms.fade_end, length(offset)));
+
+    return vec4<f32>(min(color, vec3<f32>(1.0, 1.0, 1.0)), alpha);
+}
@@ -5,15 +5,23 @@ struct Uniforms {
 
     light_color: vec3<f32>;
     light_dir: vec3<f32>;
+    shadow_strength: f32;
+    light_view_proj: mat4x4<f32>;
+
+    camera_position: vec3<f32>;
+    fog_start: f32;
+    fog_color: vec3<f32>;
+    fog_end: f32;
 };
 
 [[group(0), binding(0)]]
 var<uniform> uniforms: Uniforms;
 
+// See ChunkVertex for how everything is packed.
 struct VertexInput {
-    [[location(0)]] position: vec3<f32>;
-    [[location(1)]] normal: vec3<f32>;
-    [[location(2)]] tex_coords: vec2<f32>;
+    [[location(0)]] packed: vec2<u32>;
+    [[location(1)]] origin: vec3<f32>;
+    [[location(2)]] scale: f32;
 };
 
 struct VertexOutput {
@@ -23,17 +31,87 @@ struct VertexOutput {
 
     [[location(2)]] light_dir: vec3<f32>;
     [[location(3)]] light_color: vec3<f32>;
+    [[location(4)]] color: vec3<f32>;
+    [[location(5)]] sky_light: f32;
+    [[location(6)]] block_light: vec3<f32>;
+    [[location(7)]] ao: f32;
+    [[location(8)]] shadow_position: vec3<f32>;
+    [[location(9)]] shadow_strength: f32;
+    [[location(10)]] world_position: vec3<f32>;
+    [[location(11)]] tile: f32;
 };
 
+fn bits(value: u32, shift: u32, count: u32) -> u32 {
+    return (value >> shift) & ((1u << count) - 1u);
+}
+
 [[stage(vertex)]]
 fn vertex(model: VertexInput) -> VertexOutput {
 
+    let first = model.packed.x;
+    let second = model.packed.y;
+
+    let local = vec3<f32>(f32(bits(first, 0u, 5u)), f32(bits(first, 5u, 5u)), f32(bits(first, 10u, 5u)) - 1.0);
+    let position = model.origin + local * model.scale;
+
+    let normal_index = bits(first, 15u, 3u);
+    let axis = normal_index / 2u;
+    var normal: vec3<f32> = vec3<f32>(0.0, 0.0, 0.0);
+    let direction = select(-1.0, 1.0, normal_index % 2u == 1u);
+    if (axis == 0u) {
+        normal.x = direction;
+    }
+    elseif (axis == 1u) {
+        normal.y = direction;
+    }
+    else {
+        normal.z = direction;
+    }
+
+    // Texture coordinates run along the face's two axes, one per block, so they repeat across merged faces.
+    var face: vec2<f32> = local.xy;
+    if (axis == 0u) {
+        face = local.yz;
+    }
+    elseif (axis == 1u) {
+        face = local.zx;
+    }
+    let orientation = bits(first, 18u, 3u);
+    if (bits(orientation, 0u, 1u) == 1u) {
+        face = face.yx;
+    }
+    let flip = vec2<f32>(select(1.0, -1.0, bits(orientation, 1u, 1u) == 1u), select(1.0, -1.0, bits(orientation, 2u, 1u) == 1u));
+
+    // How bright a corner is for each amount of occlusion.
+    var ao: f32 = 1.0;
+    let occlusion = bits(first, 21u, 2u);
+    if (occlusion == 0u) {
+        ao = 0.4;
+    }
+    elseif (occlusion == 1u) {
+        ao = 0.6;
+    }
+    elseif (occlusion == 2u) {
+        ao = 0.8;
+    }
+
     var out: VertexOutput;
-    out.tex_coords = model.tex_coords;
-    out.clip_position = uniforms.view_proj * vec4<f32>(model.position, 1.0);
-    out.normal = model.normal;
+    out.tex_coords = face * flip;
+    out.clip_position = uniforms.view_proj * vec4<f32>(position, 1.0);
+    out.normal = normal;
     out.light_dir = uniforms.light_dir;
     out.light_color = uniforms.light_color;
+    out.color = vec3<f32>(f32(bits(second, 16u, 5u)) / 31.0, f32(bits(second, 21u, 6u)) / 63.0, f32(bits(second, 27u, 5u)) / 31.0);
+    out.sky_light = f32(bits(second, 0u, 4u)) / 15.0;
+    out.block_light = vec3<f32>(f32(bits(second, 4u, 4u)), f32(bits(second, 8u, 4u)), f32(bits(second, 12u, 4u))) / 15.0;
+    out.ao = ao;
+
+    // Nudged out along the normal so surfaces don't shadow themselves.
+    let shadow_clip = uniforms.light_view_proj * vec4<f32>(position + normal * 0.05, 1.0);
+    out.shadow_position = shadow_clip.xyz / shadow_clip.w;
+    out.shadow_strength = uniforms.shadow_strength;
+    out.world_position = position;
+    out.tile = f32(bits(first, 23u, 9u));
     return out;
 
 }
@@ -44,17 +122,98 @@ var chunk_texture: texture_2d<f32>;
 [[group(1), binding(1)]]
 var chunk_sampler: sampler;
 
-[[stage(fragment)]]
-fn fragment(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+// Texture coordinates are in blocks, each block gets the whole tile.
+// Tiles are 16 pixels wide, numbered along each row of the atlas.
+fn atlas_coords(in: VertexOutput) -> vec2<f32> {
+    let dimensions = textureDimensions(chunk_texture);
+    let size = vec2<f32>(f32(dimensions.x), f32(dimensions.y)) / 16.0;
+    let tile = u32(round(in.tile));
+    let columns = u32(size.x);
+    let corner = vec2<f32>(f32(tile % columns), f32(tile / columns));
+    return (corner + fract(in.tex_coords)) / size;
+}
+
+// Each light level is 80% as bright as the one above it, and level 0 is fully dark.
+fn light_curve(level: f32) -> f32 {
+    if (level <= 0.0) {
+        return 0.0;
+    }
+    return pow(0.8, (1.0 - level) * 15.0);
+}
+
+[[group(2), binding(0)]]
+var shadow_map: texture_depth_2d;
+
+[[group(2), binding(1)]]
+var shadow_sampler: sampler_comparison;
+
+// How lit a fragment is by the sun or moon, 0..1, averaged over a 3x3 grid of shadow map texels (PCF).
+fn shadow(in: VertexOutput) -> f32 {
+
+    // Clip space to texture coordinates, which have y pointing down.
+    let uv = vec2<f32>(in.shadow_position.x * 0.5 + 0.5, in.shadow_position.y * -0.5 + 0.5);
+    let depth = in.shadow_position.z;
+
+    // Anything outside the shadow map is lit.
+    if (uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0 || depth > 1.0) {
+        return 1.0;
+    }
+
+    let texel_size = 1.0 / f32(textureDimensions(shadow_map).x);
+
+    var lit: f32 = 0.0;
+    for (var x: i32 = -1; x <= 1; x = x + 1) {
+        for (var y: i32 = -1; y <= 1; y = y + 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel_size;
+            lit = lit + textureSampleCompare(shadow_map, shadow_sampler, uv + offset, depth);
+        }
+    }
+    lit = lit / 9.0;
+
+    return mix(1.0, lit, in.shadow_strength);
+}
+
+fn shade(in: VertexOutput, tex_color: vec4<f32>) -> vec4<f32> {
+
+    let sky_strength = light_curve(in.sky_light);
+    let block_color = vec3<f32>(light_curve(in.block_light.r), light_curve(in.block_light.g), light_curve(in.block_light.b));
 
-    let tex_color = textureSample(chunk_texture, chunk_sampler, in.tex_coords);
     let ambient_strength = 0.1;
-    let ambient_color = in.light_color * ambient_strength;
+    let ambient_color = in.light_color * ambient_strength * sky_strength;
 
-    let diffuse_strength = max(dot(in.light_dir, in.normal), 0.0);
+    let diffuse_strength = max(dot(in.light_dir, in.normal), 0.0) * sky_strength * shadow(in);
     let diffuse_color = in.light_color * diffuse_strength;
 
-    let result = (ambient_color + diffuse_color) * tex_color.rgb;
+    let result = max(ambient_color + diffuse_color, block_color) * in.ao * tex_color.rgb * in.color;
+
+    return vec4<f32>(result, tex_color.a);
+}
 
-    return vec4<f32>(result, 1.0);
-}
\ No newline at end of file
+// Blends towards the fog color the further a fragment is from the camera.
+fn fog(in: VertexOutput, color: vec3<f32>) -> vec3<f32> {
+
+    let camera_distance = length(in.world_position - uniforms.camera_position);
+    let amount = smoothStep(uniforms.fog_start, uniforms.fog_end, camera_distance);
+    return mix(color, uniforms.fog_color, vec3<f32>(amount, amount, amount));
+}
+
+// Opaque and cutout blocks, anything below half alpha is cut out.
+[[stage(fragment)]]
+fn fragment(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+
+    let tex_color = textureSample(chunk_texture, chunk_sampler, atlas_coords(in));
+    if (tex_color.a < 0.5) {
+        discard;
+    }
+
+    return vec4<f32>(fog(in, shade(in, tex_color).rgb), 1.0);
+}
+
+// Translucent blocks, blended with what's behind them.
+[[stage(fragment)]]
+fn fragment_translucent(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+
+    let tex_color = textureSample(chunk_texture, chunk_sampler, atlas_coords(in));
+    let color = shade(in, tex_color);
+    return vec4<f32>(fog(in, color.rgb), color.a);
+}
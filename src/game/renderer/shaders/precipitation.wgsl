@@ -0,0 +1,95 @@
+
+[[block]]
+struct Uniforms {
+    view_proj: mat4x4<f32>;
+
+    camera_position: vec3<f32>;
+    time: f32;
+    color: vec3<f32>;
+    fall_speed: f32;
+    particle_size: vec2<f32>;
+    // How far particles sway side to side, for snow.
+    sway: f32;
+    area_size: f32;
+    // Grid position of the heightmap's first column.
+    heightmap_origin: vec2<i32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> uniforms: Uniforms;
+
+// Height of the top of the highest block in each column around the camera.
+[[group(0), binding(1)]]
+var heightmap: texture_2d<f32>;
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] quad_position: vec2<f32>;
+    [[location(1)]] alpha: f32;
+};
+
+fn hash(n: f32) -> f32 {
+    return fract(sin(n) * 43758.5453);
+}
+
+// Every particle is a camera facing quad, placed from its index alone and wrapped around the camera
+// so the same few particles are endlessly reused as they fall and as the camera moves.
+[[stage(vertex)]]
+fn vertex([[builtin(vertex_index)]] index: u32, [[builtin(instance_index)]] instance: u32) -> VertexOutput {
+
+    let id = f32(instance);
+    let size = uniforms.area_size;
+
+    let start = vec3<f32>(hash(id * 1.31), hash(id * 2.17), hash(id * 3.73)) * size;
+    let speed = uniforms.fall_speed * (0.8 + 0.4 * hash(id * 4.19));
+    let phase = hash(id * 5.87) * 6.2831;
+
+    var position: vec3<f32> = start - vec3<f32>(0.0, uniforms.time * speed, 0.0);
+    position.x = position.x + sin(uniforms.time + phase) * uniforms.sway;
+    position.z = position.z + cos(uniforms.time * 0.7 + phase) * uniforms.sway;
+
+    // Wraps the particle into the box around the camera.
+    let corner = uniforms.camera_position - vec3<f32>(size, size, size) * 0.5;
+    position = corner + (position - corner) - floor((position - corner) / size) * size;
+
+    // Particles below the highest block of their column are under a roof, so they're hidden.
+    let column = vec2<i32>(i32(floor(position.x)), i32(ceil(position.z))) - uniforms.heightmap_origin;
+    let dimensions = textureDimensions(heightmap);
+    var alpha: f32 = 1.0;
+    if (column.x >= 0 && column.y >= 0 && column.x < dimensions.x && column.y < dimensions.y) {
+        let ground = textureLoad(heightmap, column, 0).r;
+        if (position.y < ground) {
+            alpha = 0.0;
+        }
+    }
+
+    // Quads turn around the y axis to face the camera, so rain streaks stay upright.
+    let to_camera = uniforms.camera_position - position;
+    let right = normalize(vec3<f32>(to_camera.z, 0.0, -to_camera.x) + vec3<f32>(0.0001, 0.0, 0.0));
+
+    let corner_x = select(-0.5, 0.5, index == 1u || index == 2u || index == 4u);
+    let corner_y = select(-0.5, 0.5, index == 2u || index == 4u || index == 5u);
+    let vertex_position = position + right * corner_x * uniforms.particle_size.x + vec3<f32>(0.0, corner_y * uniforms.particle_size.y, 0.0);
+
+    // Fades out towards the edges of the box, so particles don't pop in.
+    let distance_fade = 1.0 - smoothStep(size * 0.3, size * 0.5, length(position - uniforms.camera_position));
+
+    var out: VertexOutput;
+    out.clip_position = uniforms.view_proj * vec4<f32>(vertex_position, 1.0);
+    out.quad_position = vec2<f32>(corner_x, corner_y) * 2.0;
+    out.alpha = alpha * distance_fade;
+    return out;
+
+}
+
+[[stage(fragment)]]
+fn fragment(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+
+    if (in.alpha <= 0.0) {
+        discard;
+    }
+
+    // Soft round edges, which only show on the wider snowflakes.
+    let edge = 1.0 - smoothStep(0.6, 1.0, length(in.quad_position));
+    return vec4<f32>(uniforms.color, 0.6 * in.alpha * edge);
+}
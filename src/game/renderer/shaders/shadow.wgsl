@@ -0,0 +1,74 @@
+
+[[block]]
+struct Uniforms {
+    light_view_proj: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> uniforms: Uniforms;
+
+// Only the position and texture coordinates are unpacked here, see ChunkVertex and the default shader.
+struct VertexInput {
+    [[location(0)]] packed: vec2<u32>;
+    [[location(1)]] origin: vec3<f32>;
+    [[location(2)]] scale: f32;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+    [[location(1)]] tile: f32;
+};
+
+fn bits(value: u32, shift: u32, count: u32) -> u32 {
+    return (value >> shift) & ((1u << count) - 1u);
+}
+
+[[stage(vertex)]]
+fn vertex(model: VertexInput) -> VertexOutput {
+
+    let first = model.packed.x;
+    let local = vec3<f32>(f32(bits(first, 0u, 5u)), f32(bits(first, 5u, 5u)), f32(bits(first, 10u, 5u)) - 1.0);
+
+    let axis = bits(first, 15u, 3u) / 2u;
+    var face: vec2<f32> = local.xy;
+    if (axis == 0u) {
+        face = local.yz;
+    }
+    elseif (axis == 1u) {
+        face = local.zx;
+    }
+    let orientation = bits(first, 18u, 3u);
+    if (bits(orientation, 0u, 1u) == 1u) {
+        face = face.yx;
+    }
+    let flip = vec2<f32>(select(1.0, -1.0, bits(orientation, 1u, 1u) == 1u), select(1.0, -1.0, bits(orientation, 2u, 1u) == 1u));
+
+    var out: VertexOutput;
+    out.tex_coords = face * flip;
+    out.tile = f32(bits(first, 23u, 9u));
+    out.clip_position = uniforms.light_view_proj * vec4<f32>(model.origin + local * model.scale, 1.0);
+    return out;
+
+}
+
+[[group(1), binding(0)]]
+var chunk_texture: texture_2d<f32>;
+
+[[group(1), binding(1)]]
+var chunk_sampler: sampler;
+
+// Cutout parts of blocks let light through, same as in the main pass.
+[[stage(fragment)]]
+fn fragment(in: VertexOutput) {
+
+    let dimensions = textureDimensions(chunk_texture);
+    let size = vec2<f32>(f32(dimensions.x), f32(dimensions.y)) / 16.0;
+    let tile = u32(round(in.tile));
+    let corner = vec2<f32>(f32(tile % u32(size.x)), f32(tile / u32(size.x)));
+
+    let tex_color = textureSample(chunk_texture, chunk_sampler, (corner + fract(in.tex_coords)) / size);
+    if (tex_color.a < 0.5) {
+        discard;
+    }
+}
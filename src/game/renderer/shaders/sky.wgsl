@@ -0,0 +1,106 @@
+
+[[block]]
+struct Uniforms {
+    inv_view_proj: mat4x4<f32>;
+
+    camera_position: vec3<f32>;
+    transition: f32;
+    sun_direction: vec3<f32>;
+    fog_amount: f32;
+    moon_direction: vec3<f32>;
+
+    zenith_color: vec3<f32>;
+    horizon_color: vec3<f32>;
+    sunset_color: vec3<f32>;
+    fog_color: vec3<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> uniforms: Uniforms;
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] screen_position: vec2<f32>;
+};
+
+// A single triangle covering the whole screen, no vertex buffer needed.
+[[stage(vertex)]]
+fn vertex([[builtin(vertex_index)]] index: u32) -> VertexOutput {
+
+    let x = f32(i32(index & 1u) * 4 - 1);
+    let y = f32(i32(index >> 1u) * 4 - 1);
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(x, y, 0.0, 1.0);
+    out.screen_position = vec2<f32>(x, y);
+    return out;
+
+}
+
+// Whether a view direction falls within a square of the given size facing the camera around 'center',
+// which is how the sun and moon are drawn as billboards without any geometry.
+fn billboard(dir: vec3<f32>, center: vec3<f32>, size: f32) -> f32 {
+
+    let facing = dot(dir, center);
+    if (facing <= 0.0) {
+        return 0.0;
+    }
+
+    // The sun and moon rotate around the z axis, so it's always perpendicular to them.
+    let tangent = vec3<f32>(0.0, 0.0, 1.0);
+    let bitangent = cross(center, tangent);
+    let u = dot(dir, tangent) / facing;
+    let v = dot(dir, bitangent) / facing;
+
+    return select(0.0, 1.0, abs(u) < size && abs(v) < size);
+}
+
+// Sparse points of light, fixed to the sky so they turn with the sun and moon.
+fn stars(dir: vec3<f32>) -> f32 {
+
+    let angle = atan2(uniforms.sun_direction.y, uniforms.sun_direction.x);
+    let c = cos(-angle);
+    let s = sin(-angle);
+    let sky_dir = vec3<f32>(dir.x * c - dir.y * s, dir.x * s + dir.y * c, dir.z);
+
+    let cell = floor(sky_dir * 150.0);
+    let hash = fract(sin(dot(cell, vec3<f32>(12.9898, 78.233, 37.719))) * 43758.5453);
+
+    return select(0.0, (hash - 0.997) / 0.003, hash > 0.997);
+}
+
+[[stage(fragment)]]
+fn fragment(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+
+    // The direction this pixel looks in, from a point on the far plane.
+    let far = uniforms.inv_view_proj * vec4<f32>(in.screen_position, 1.0, 1.0);
+    let dir = normalize(far.xyz / far.w - uniforms.camera_position);
+
+    let height = max(dir.y, 0.0);
+    var color: vec3<f32> = mix(uniforms.horizon_color, uniforms.zenith_color, vec3<f32>(sqrt(height), sqrt(height), sqrt(height)));
+
+    // Sunrise and sunset tint the horizon on the side the sun is on, strongest halfway through the transition.
+    let transitioning = 1.0 - abs(uniforms.transition * 2.0 - 1.0);
+
+    // The sun only moves across x, so that's the side it rises and sets on.
+    let horizontal = normalize(vec2<f32>(dir.x, dir.z) + vec2<f32>(0.0001, 0.0));
+    let sun_side = max(horizontal.x * sign(uniforms.sun_direction.x), 0.0);
+    let tint = transitioning * (1.0 - height) * (1.0 - height) * (0.4 + 0.6 * sun_side);
+    color = mix(color, uniforms.sunset_color, vec3<f32>(tint, tint, tint));
+
+    let night = 1.0 - uniforms.transition;
+    let star = stars(dir) * night * smoothStep(-0.05, 0.15, dir.y);
+    color = color + vec3<f32>(star, star, star);
+
+    let sun_glow = pow(max(dot(dir, uniforms.sun_direction), 0.0), 64.0) * 0.4;
+    color = color + vec3<f32>(1.0, 0.8, 0.5) * sun_glow;
+
+    let sun = billboard(dir, uniforms.sun_direction, 0.08);
+    let moon = billboard(dir, uniforms.moon_direction, 0.05);
+    color = mix(color, vec3<f32>(1.0, 0.95, 0.7), vec3<f32>(sun, sun, sun));
+    color = mix(color, vec3<f32>(0.85, 0.88, 0.95), vec3<f32>(moon, moon, moon));
+
+    color = mix(color, uniforms.fog_color, vec3<f32>(uniforms.fog_amount, uniforms.fog_amount, uniforms.fog_amount));
+
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,256 @@
+use wgpu::{Queue, util::DeviceExt};
+use cgmath::{InnerSpace, Matrix4, Point3, Vector3, Vector4};
+
+use super::{texture, vertex::{ChunkInstance, ChunkVertex}, WIREFRAME_MODE};
+use crate::game::camera::{Camera, OPENGL_TO_WGPU_MATRIX};
+
+// Resolution of the shadow map on both axes.
+pub const SHADOW_MAP_SIZE: u32 = 2048;
+
+// How far from the camera shadows are drawn, in blocks.
+pub const SHADOW_DISTANCE: f32 = 64.0;
+
+// Extra depth behind the fitted frustum so terrain outside the view can still cast shadows into it.
+pub const CASTER_MARGIN: f32 = 64.0;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ShadowData {
+    pub light_view_proj: [[f32; 4]; 4],
+}
+
+// A depth map of the loaded terrain as seen from the sun or moon, rendered before the main pass
+// and sampled by the default shader to find what's in shadow.
+pub struct ShadowMap {
+    // Bound together with its comparison sampler for the main pass.
+    pub texture: texture::Texture,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub pipeline: wgpu::RenderPipeline,
+
+    pub data: ShadowData,
+    pub buffer: wgpu::Buffer,
+    pub uniform_bind_group: wgpu::BindGroup,
+
+    // Normalized direction pointing towards the light casting shadows.
+    pub light_direction: Vector3<f32>,
+}
+
+impl ShadowMap {
+
+    pub fn new(device: &wgpu::Device, texture_bind_group_layout: &wgpu::BindGroupLayout) -> ShadowMap {
+        use cgmath::SquareMatrix;
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: SHADOW_MAP_SIZE,
+                height: SHADOW_MAP_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: texture::Texture::DEPTH_FORMAT,
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        // Linear filtering on a comparison sampler blends the four nearest comparisons,
+        // which softens the edges a bit more on top of the shader's filtering.
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let data = ShadowData {
+            light_view_proj: Matrix4::identity().into(),
+        };
+
+        let buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(&[data]),
+                usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            }
+        );
+
+        let uniform_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }
+            ]
+        });
+
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &uniform_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                }
+            ],
+            label: None,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: true,
+                    },
+                    count: None,
+                }
+            ]
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+            label: None,
+        });
+
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/shadow.wgsl").into()),
+            flags: wgpu::ShaderFlags::empty(),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&uniform_bind_group_layout, texture_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        // Only depth is written, the fragment stage is just there to cut out leaves and glass.
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vertex",
+                buffers: &[ChunkVertex::layout(), ChunkInstance::layout()],
+            },
+
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fragment",
+                targets: &[],
+            }),
+
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                clamp_depth: false,
+                polygon_mode: if WIREFRAME_MODE { wgpu::PolygonMode::Line} else { wgpu::PolygonMode::Fill },
+                conservative: false,
+            },
+            multisample: wgpu::MultisampleState::default(),
+
+            // Biased to keep surfaces from shadowing themselves (shadow acne).
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+            }),
+        });
+
+        ShadowMap {
+            texture: texture::Texture { texture, view, sampler, bind_group: Some(bind_group) },
+            bind_group_layout,
+            pipeline,
+            data,
+            buffer,
+            uniform_bind_group,
+            light_direction: Vector3::new(0.0, 1.0, 0.0),
+        }
+    }
+
+    pub fn write(&self, queue: &Queue) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.data]));
+    }
+
+    // Fits an orthographic frustum around the part of the camera's view that gets shadows.
+    // The frustum is sized by a bounding sphere so it doesn't change size as the camera turns,
+    // and snapped to whole texels so shadow edges don't shimmer as the camera moves.
+    pub fn build_light_view_projection_matrix(&self, camera: &Camera) -> Matrix4<f32> {
+
+        let (forward, right, up) = camera.get_headings();
+        let aspect = camera.width as f32 / camera.height as f32;
+        let tan_half_fovy = (camera.fovy.0 * 0.5).tan();
+
+        let mut corners: Vec<Vector3<f32>> = Vec::new();
+        for distance in [camera.near, SHADOW_DISTANCE].iter() {
+            let half_height = distance * tan_half_fovy;
+            let half_width = half_height * aspect;
+            for (x, y) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].iter() {
+                corners.push(camera.position + forward * *distance + right * (half_width * x) + up * (half_height * y));
+            }
+        }
+
+        let center = corners.iter().fold(Vector3::new(0.0, 0.0, 0.0), |sum, c| sum + c) / corners.len() as f32;
+        let radius = corners.iter().map(|c| (c - center).magnitude()).fold(0.0, f32::max).ceil();
+
+        // The sun and moon rotate around the z axis, so it's always perpendicular to their direction.
+        let view = Matrix4::look_to_rh(Point3::new(0.0, 0.0, 0.0), -self.light_direction, Vector3::unit_z());
+
+        let texel_size = (radius * 2.0) / SHADOW_MAP_SIZE as f32;
+        let light_center = view * Vector4::new(center.x, center.y, center.z, 1.0);
+        let x = (light_center.x / texel_size).floor() * texel_size;
+        let y = (light_center.y / texel_size).floor() * texel_size;
+
+        // The view looks down -z, so depths are the negated z.
+        let near = -light_center.z - radius - CASTER_MARGIN;
+        let far = -light_center.z + radius;
+
+        let projection = cgmath::ortho(x - radius, x + radius, y - radius, y + radius, near, far);
+
+        OPENGL_TO_WGPU_MATRIX * projection * view
+    }
+}
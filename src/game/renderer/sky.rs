@@ -0,0 +1,166 @@
+use wgpu::{Queue, util::DeviceExt};
+use cgmath::{Matrix4, SquareMatrix};
+
+use super::texture;
+use crate::game::camera::Camera;
+
+// IMPORANT!! wgpu requires that uniforms are spaced by 16 bytes!
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SkyData {
+    pub inv_view_proj: [[f32; 4]; 4],
+
+    pub camera_position: [f32; 3],
+    // 0..1, night..day
+    pub transition: f32,
+    pub sun_direction: [f32; 3],
+    // How much the sky is covered by fog, for when the camera is underwater or inside a block.
+    pub fog_amount: f32,
+    pub moon_direction: [f32; 3],
+    pub padding_0: u32,
+
+    pub zenith_color: [f32; 3],
+    pub padding_1: u32,
+    pub horizon_color: [f32; 3],
+    pub padding_2: u32,
+    pub sunset_color: [f32; 3],
+    pub padding_3: u32,
+    pub fog_color: [f32; 3],
+    pub padding_4: u32,
+}
+
+// The sky is drawn over the whole screen before any terrain, as a gradient with the sun, moon and stars on it.
+pub struct Sky {
+    pub pipeline: wgpu::RenderPipeline,
+    pub data: SkyData,
+    pub buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl Sky {
+
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Sky {
+
+        let data = SkyData {
+            inv_view_proj: Matrix4::identity().into(),
+
+            camera_position: [0.0, 0.0, 0.0],
+            transition: 1.0,
+            sun_direction: [0.0, 1.0, 0.0],
+            fog_amount: 0.0,
+            moon_direction: [0.0, -1.0, 0.0],
+            padding_0: 0,
+
+            zenith_color: [0.0, 0.0, 0.0],
+            padding_1: 0,
+            horizon_color: [0.0, 0.0, 0.0],
+            padding_2: 0,
+            sunset_color: [0.0, 0.0, 0.0],
+            padding_3: 0,
+            fog_color: [0.0, 0.0, 0.0],
+            padding_4: 0,
+        };
+
+        let buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(&[data]),
+                usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            }
+        );
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }
+            ]
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                }
+            ],
+            label: None,
+        });
+
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/sky.wgsl").into()),
+            flags: wgpu::ShaderFlags::empty(),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vertex",
+                buffers: &[],
+            },
+
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fragment",
+                targets: &[
+                    wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrite::ALL,
+                    },
+                ],
+            }),
+
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                clamp_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            multisample: wgpu::MultisampleState::default(),
+
+            // Shares the main pass' depth buffer, but stays behind everything.
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+        });
+
+        Sky { pipeline, data, buffer, bind_group }
+    }
+
+    pub fn write(&self, queue: &Queue) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.data]));
+    }
+
+    pub fn update_camera(&mut self, camera: &Camera) {
+        let view_proj = camera.build_view_projection_matrix();
+        self.data.inv_view_proj = view_proj.invert().unwrap_or(Matrix4::identity()).into();
+        self.data.camera_position = camera.position.into();
+    }
+}
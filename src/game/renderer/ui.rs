@@ -49,7 +49,7 @@ impl UIRect {
         vertices.push(Vertex { position: p2.into(), normal: [1.0, 0.0, 0.0], tex_coords: [0.0, 0.0] });
         vertices.push(Vertex { position: p3.into(), normal: [1.0, 0.0, 0.0], tex_coords: [1.0, 0.0] });
 
-        let mut indices: Vec<u16> = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
         indices.push(0);
         indices.push(1);
         indices.push(2);
@@ -11,6 +11,13 @@ pub struct DefaultData {
     pub light_color: [f32; 3],
     pub padding: u32,
     pub light_direction: [f32; 3],
+    pub shadow_strength: f32,
+    pub light_view_proj: [[f32; 4]; 4],
+
+    pub camera_position: [f32; 3],
+    pub fog_start: f32,
+    pub fog_color: [f32; 3],
+    pub fog_end: f32,
 }
 
 #[repr(C)]
@@ -37,6 +44,13 @@ impl DefaultUniforms {
             light_color: Color::from_rgb(1.0, 1.0, 1.0).into(),
             padding: 0,
             light_direction: Vector3::new(0.0, 1.0, 0.0).normalize().into(),
+            shadow_strength: 1.0,
+            light_view_proj: cgmath::Matrix4::identity().into(),
+
+            camera_position: [0.0, 0.0, 0.0],
+            fog_start: 0.0,
+            fog_color: Color::from_rgb(1.0, 1.0, 1.0).into(),
+            fog_end: 1.0,
         };
 
         let buffer = device.create_buffer_init(
@@ -52,7 +66,7 @@ impl DefaultUniforms {
             entries: &[
                 wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStage::VERTEX,
+                    visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
@@ -37,4 +37,133 @@ impl Vertex {
         }
 
     }
-}
\ No newline at end of file
+}
+
+// Atlas tiles a chunk vertex can point at, with 9 bits for the tile.
+pub const MAX_TILES: u32 = 1 << 9;
+
+// A chunk vertex packed into two u32s, unpacked again in the shaders.
+// Positions are relative to the chunk, which is drawn with its origin as instance data.
+//
+// First:  x, y and z (5 bits each, 0..16), normal (3), texture orientation (3), ambient occlusion (2), atlas tile (9).
+// Second: sky, red, green and blue light (4 bits each, 0..15), then the tint as 5 bits red, 6 green and 5 blue.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ChunkVertex {
+    pub data: [u32; 2],
+}
+
+impl ChunkVertex {
+
+    // 'position' is within the chunk. Blocks span -1 on z from their place in the grid, so z runs from -1 to 15.
+    // 'normal' is the axis times two, plus one if it points along the positive side of it.
+    // 'orientation' says how texture coordinates follow the face, see Chunk::texture_orientation.
+    // 'ao' is 0 for fully occluded to 3 for not occluded at all, and 'light' is 0..1 per channel.
+    pub fn new(position: [i32; 3], normal: u32, orientation: u32, ao: u8, tile: u32, light: [f32; 4], tint: [f32; 3]) -> ChunkVertex {
+
+        debug_assert!(position[0] >= 0 && position[0] <= 16, "x {} outside the chunk", position[0]);
+        debug_assert!(position[1] >= 0 && position[1] <= 16, "y {} outside the chunk", position[1]);
+        debug_assert!(position[2] >= -1 && position[2] <= 15, "z {} outside the chunk", position[2]);
+        debug_assert!(normal < 6, "normal {} isn't one of the six", normal);
+        debug_assert!(orientation < 8, "orientation {} doesn't fit in 3 bits", orientation);
+        debug_assert!(ao <= 3, "ambient occlusion {} doesn't fit in 2 bits", ao);
+        debug_assert!(tile < MAX_TILES, "tile {} is past the last one a vertex can address", tile);
+        debug_assert!(light.iter().chain(tint.iter()).all(|c| (0.0..=1.0).contains(c)), "light {:?} or tint {:?} outside 0..1", light, tint);
+
+        let level = |l: f32| (l * 15.0).round() as u32;
+        let channel = |c: f32, max: f32| (c * max).round() as u32;
+
+        let first = position[0] as u32
+            | (position[1] as u32) << 5
+            | ((position[2] + 1) as u32) << 10
+            | normal << 15
+            | orientation << 18
+            | (ao as u32) << 21
+            | tile << 23;
+
+        let second = level(light[0])
+            | level(light[1]) << 4
+            | level(light[2]) << 8
+            | level(light[3]) << 12
+            | channel(tint[0], 31.0) << 16
+            | channel(tint[1], 63.0) << 21
+            | channel(tint[2], 31.0) << 27;
+
+        ChunkVertex { data: [first, second] }
+    }
+
+    pub fn layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<ChunkVertex>() as u64,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Uint32x2,
+                    offset: 0,
+                    shader_location: 0,
+                },
+            ]
+        }
+    }
+}
+
+// Per chunk data, one instance of it for every chunk drawn.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ChunkInstance {
+    // World position of the chunk's first block.
+    pub origin: [f32; 3],
+    // How many blocks across each block of the mesh is, 1 except for far off terrain.
+    pub scale: f32,
+}
+
+impl ChunkInstance {
+
+    pub fn layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<ChunkInstance>() as u64,
+            step_mode: wgpu::InputStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x3,
+                    offset: 0,
+                    shader_location: 1,
+                },
+
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32,
+                    offset: std::mem::size_of::<[f32; 3]>() as u64,
+                    shader_location: 2,
+                },
+            ]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_vertices_are_a_quarter_of_the_size() {
+        assert_eq!(std::mem::size_of::<ChunkVertex>(), 8);
+        assert_eq!(std::mem::size_of::<Vertex>(), 32);
+    }
+
+    #[test]
+    fn fields_are_packed_where_the_shader_reads_them() {
+        let vertex = ChunkVertex::new([16, 3, -1], 5, 6, 2, MAX_TILES - 1, [1.0, 0.0, 0.0, 1.0], [1.0, 0.0, 1.0]);
+        let [first, second] = vertex.data;
+
+        assert_eq!(first & 31, 16);
+        assert_eq!(first >> 5 & 31, 3);
+        assert_eq!(first >> 10 & 31, 0);
+        assert_eq!(first >> 15 & 7, 5);
+        assert_eq!(first >> 18 & 7, 6);
+        assert_eq!(first >> 21 & 3, 2);
+        assert_eq!(first >> 23, MAX_TILES - 1);
+
+        assert_eq!(second & 0xffff, 0xf00f);
+        assert_eq!(second >> 16, 31 | 31 << 11);
+    }
+}
@@ -1,12 +1,18 @@
-use std::{cmp::min, collections::HashMap, f32::consts::PI, time::{SystemTime, UNIX_EPOCH}, u64};
+pub mod climate;
+pub mod lighting;
+pub mod lod;
+pub mod queue;
+pub mod raycast;
+pub mod visibility;
+pub mod weather;
+pub mod workers;
+
+use std::{collections::HashMap, f32::consts::PI, sync::Arc, time::{SystemTime, UNIX_EPOCH}, u64};
 
 use cgmath::{Matrix3, MetricSpace, Rad, Vector3, VectorSpace, num_traits::clamp};
-use image::DynamicImage;
 use bracket_noise::prelude::*;
-use std::sync::mpsc;
-use rayon::prelude::*;
 
-use super::{CHUNKS_GEN_PER_FRAME, RENDER_DISTANCE, camera::Camera, chunk::{self, Chunk, block::{Block, BlockList}}, color::Color, renderer::{Renderer, mesh::Mesh}};
+use super::{RENDER_DISTANCE, camera::Camera, chunk::{CHUNK_SIZE, Chunk, block::{Block, BlockList, BlockTable}}, color::Color, renderer::{Renderer, clouds::CLOUD_MAP_SIZE}};
 
 fn smoothstep(edge0: f32, edge1: f32, input: f32) -> f32 {
     let x = clamp((input - edge0) / (edge1 - edge0), 0.0, 1.0);
@@ -15,20 +21,44 @@ fn smoothstep(edge0: f32, edge1: f32, input: f32) -> f32 {
 
 pub struct World {
     pub chunks: HashMap<Vector3<i32>, Chunk>,
-    pub chunk_queue: Vec<Vector3<i32>>,
+    pub chunk_queue: queue::ChunkQueue,
     pub seed: u64,
-    pub noise: FastNoise,
-    pub block_list: BlockList,
-    pub block_atlas: DynamicImage,
+    pub block_list: Arc<BlockList>,
+    pub block_table: Arc<BlockTable>,
 
     pub sky_color: Color,
     pub day_sky_color: Color,
     pub night_sky_color: Color,
+    pub day_zenith_color: Color,
+    pub night_zenith_color: Color,
+    pub sunset_color: Color,
+
+    // What distant terrain fades into, usually the sky.
+    pub fog_color: Color,
 
     pub daylight_color: Color,
     pub moonlight_color: Color,
 
     pub time: f64,
+
+    // Height of the bottom of the cloud layer, and how fast it drifts in blocks per second.
+    pub cloud_altitude: f32,
+    pub wind: Vector3<f32>,
+
+    pub weather: weather::WeatherState,
+
+    // Interpolate light across faces instead of lighting each face flatly.
+    pub smooth_lighting: bool,
+
+    // Far off terrain, by level of detail and position, see LodRegion.
+    pub lod_regions: HashMap<(u32, Vector3<i32>), lod::LodRegion>,
+    // How far out each level of detail reaches, in chunks, see World::select_lod_regions.
+    // Full detail reaches RENDER_DISTANCE, and each of these should be further than the last.
+    pub lod_distances: [f32; lod::LOD_LEVELS],
+
+    pub workers: workers::Workers,
+    // Counts up with every mesh job, see Chunk::mesh_job.
+    pub next_mesh_job: u64,
 }
 
 impl World {
@@ -39,6 +69,14 @@ impl World {
     // the percent of day used for transitioning between night and day.
     pub const TRANSITION_PORTION: f32 = 0.05;
 
+    // Where fog starts and fully covers terrain, as portions of the render distance.
+    pub const FOG_START_PORTION: f32 = 0.6;
+    pub const FOG_END_PORTION: f32 = 0.95;
+
+    // Fog distances when the camera is inside a block with fog of its own, like water, in blocks.
+    pub const BLOCK_FOG_START: f32 = 0.0;
+    pub const BLOCK_FOG_END: f32 = 16.0;
+
     pub fn new() -> World {
 
         let seed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64;
@@ -50,22 +88,43 @@ impl World {
         noise.set_fractal_lacunarity(2.0);
         noise.set_frequency(0.3);
 
+        let block_list = Arc::new(BlockList::initialize());
+        let block_table = Arc::new(BlockTable::new(&block_list));
+        let workers = workers::Workers::new(Arc::new(noise), Arc::new(climate::Climate::new(seed)), block_list.clone(), block_table.clone());
+
         World {
             chunks: HashMap::new(),
-            chunk_queue: Vec::new(),
+            chunk_queue: queue::ChunkQueue::new(),
             seed,
-            noise,
-            block_list: BlockList::initialize(),
-            block_atlas: image::open("./src/game/data/blocks/atlas.png").unwrap(),
+            block_list,
+            block_table,
 
             sky_color: Color::from_u32(120, 190, 255),
             day_sky_color: Color::from_u32(120, 190, 255),
             night_sky_color: Color::from_u32(4, 4, 10),
+            day_zenith_color: Color::from_u32(60, 120, 230),
+            night_zenith_color: Color::from_u32(1, 1, 4),
+            sunset_color: Color::from_u32(255, 120, 50),
+
+            fog_color: Color::from_u32(120, 190, 255),
 
             daylight_color: Color::from_rgb(1.0, 1.0, 1.0),
             moonlight_color: Color::from_u32(64, 90, 128),
 
             time: 0.0,
+
+            cloud_altitude: 96.0,
+            wind: Vector3::new(2.0, 0.0, 1.0),
+
+            weather: weather::WeatherState::new(seed.wrapping_add(4)),
+
+            smooth_lighting: true,
+
+            lod_regions: HashMap::new(),
+            lod_distances: [16.0, 24.0, 32.0],
+
+            workers,
+            next_mesh_job: 0,
         }
     }
 
@@ -95,97 +154,66 @@ impl World {
             transition = 1.0 - day_trans;
         }
 
-        self.sky_color = Color::lerp(self.night_sky_color, self.day_sky_color, transition);
-        renderer.default_uniforms.data.light_color = Color::lerp(self.moonlight_color, self.daylight_color, transition).into();
-        renderer.default_uniforms.data.light_direction = moonlight_direction.lerp(sunlight_direction, transition).into();
-
-
-        // Multithreaded chunk generation.
-        let gen_count = min(self.chunk_queue.len(), CHUNKS_GEN_PER_FRAME as usize);
-        let gen_list = &self.chunk_queue[0..gen_count];
-
-        let (tx, rx) = mpsc::channel();
-
-        gen_list.par_iter()
-            .for_each_with(tx, |s, pos| {
-
-                let chunk = Chunk::new(*pos, self);
-                s.send((*pos, chunk)).unwrap();
-
-            });
-        
-        for _ in 0..gen_count {
-
-            let (p, c): (Vector3<i32>, Chunk) = rx.recv().unwrap();
-            self.chunks.insert(p, c);
-            self.chunk_queue.remove(0);
-
-            match self.chunks.get_mut(&(p + Chunk::UP)) {
-                Some(chunk) => chunk.should_regen_mesh = true,
-                None => (),
-            }
-
-            match self.chunks.get_mut(&(p + Chunk::DOWN)) {
-                Some(chunk) => chunk.should_regen_mesh = true,
-                None => (),
-            }
+        self.weather.update(delta);
 
-            match self.chunks.get_mut(&(p + Chunk::LEFT)) {
-                Some(chunk) => chunk.should_regen_mesh = true,
-                None => (),
-            }
-
-            match self.chunks.get_mut(&(p + Chunk::RIGHT)) {
-                Some(chunk) => chunk.should_regen_mesh = true,
-                None => (),
-            }
-
-            match self.chunks.get_mut(&(p + Chunk::FORWARD)) {
-                Some(chunk) => chunk.should_regen_mesh = true,
-                None => (),
-            }
-
-            match self.chunks.get_mut(&(p + Chunk::BACKWARD)) {
-                Some(chunk) => chunk.should_regen_mesh = true,
-                None => (),
-            }
-
-        }
+        self.sky_color = self.apply_weather(Color::lerp(self.night_sky_color, self.day_sky_color, transition));
+        renderer.default_uniforms.data.light_color = self.apply_weather(Color::lerp(self.moonlight_color, self.daylight_color, transition)).into();
+        renderer.default_uniforms.data.light_direction = moonlight_direction.lerp(sunlight_direction, transition).into();
 
-        // Multithreaded chunk meshing.
-        let mut meshes = Vec::new();
-        for (pos, chunk) in self.chunks.iter() {
-            if chunk.should_regen_mesh {
-                meshes.push((*pos, chunk));
+        // The sky's color at the horizon is 'sky_color', so fog blends into it.
+        renderer.sky.data.transition = transition;
+        renderer.sky.data.sun_direction = sunlight_direction.into();
+        renderer.sky.data.moon_direction = moonlight_direction.into();
+        renderer.sky.data.zenith_color = self.apply_weather(Color::lerp(self.night_zenith_color, self.day_zenith_color, transition)).into();
+        renderer.sky.data.horizon_color = self.sky_color.into();
+        renderer.sky.data.sunset_color = self.sunset_color.into();
+
+        // The cloud map is one tile, placed over the camera as it drifts with the wind.
+        let tile_size = renderer.clouds.data.tile_size;
+        let drift_x = ((self.wind.x as f64 * self.time) % tile_size as f64) as f32;
+        let drift_z = ((self.wind.z as f64 * self.time) % tile_size as f64) as f32;
+        let tile_x = drift_x + ((camera.position.x - drift_x) / tile_size).floor() * tile_size;
+        let tile_z = drift_z + ((camera.position.z - drift_z) / tile_size).floor() * tile_size;
+        renderer.clouds.data.offset = [tile_x, self.cloud_altitude, tile_z];
+        renderer.clouds.data.light_color = renderer.default_uniforms.data.light_color;
+
+        // Shadows are cast by whichever of the sun or moon is up, and fade out while they trade places.
+        // Overcast weather hides them too.
+        renderer.shadow_map.light_direction = if transition > 0.5 { sunlight_direction } else { moonlight_direction };
+        renderer.default_uniforms.data.shadow_strength = (transition * 2.0 - 1.0).abs() * (1.0 - self.weather.darkness());
+
+        self.update_precipitation(renderer, camera, delta);
+
+        // Fog hides where chunks stop loading, and closes in when the camera is in water or lava or inside a block.
+        let render_distance = self.view_distance() * CHUNK_SIZE as f32;
+        let camera_block = Vector3::new(camera.position.x.floor() as i32, camera.position.y.floor() as i32, camera.position.z.ceil() as i32);
+        let camera_state = self.get_state_at(camera_block);
+        let block_fog = camera_state.and_then(|state| self.block_list.get_block_from_state(state)?.fog);
+        let (fog_color, fog_start, fog_end) = match (block_fog, camera_state) {
+            (Some([r, g, b]), _) => {
+                (Color::lerp(Color::from_rgb(0.0, 0.0, 0.0), Color::from_u32(r as u32, g as u32, b as u32), transition * 0.5 + 0.5), World::BLOCK_FOG_START, World::BLOCK_FOG_END)
+            },
+            (None, Some(state)) if self.block_list.is_opaque(state) => {
+                (Color::from_rgb(0.0, 0.0, 0.0), 0.0, 1.0)
+            },
+            _ => {
+                (self.sky_color, render_distance * World::FOG_START_PORTION, render_distance * World::FOG_END_PORTION)
             }
-        }
-
-        let (tx, rx) = mpsc::channel();
+        };
 
+        self.fog_color = fog_color;
+        renderer.default_uniforms.data.camera_position = camera.position.into();
+        renderer.default_uniforms.data.fog_color = fog_color.into();
+        renderer.default_uniforms.data.fog_start = fog_start;
+        renderer.default_uniforms.data.fog_end = fog_end;
 
-        let count = meshes.len();
-        meshes.par_drain(..meshes.len())
-            .for_each_with(tx, |tx, (pos, chunk)| {
+        // Close fog hides the sky as well.
+        renderer.sky.data.fog_color = fog_color.into();
+        renderer.sky.data.fog_amount = if fog_end < render_distance * World::FOG_START_PORTION { 1.0 } else { 0.0 };
 
-                let (vertices, indices) = chunk.generate_mesh_parts(self);
-                let mesh = Mesh::new(&renderer.device, vertices, indices);
-                tx.send((pos, mesh)).unwrap();
-
-            });
-        
-        for _ in 0..count {
-
-            let (pos, mesh) = rx.recv().unwrap();
-            match self.chunks.get_mut(&pos) {
-                Some(chunk) => {
-                    chunk.mesh = Some(mesh);
-                    chunk.should_regen_mesh = false;
-                },
-                None => (),
-            }
-
-        }
 
+        self.update_chunks(renderer, camera);
+        self.update_lod(renderer, camera);
     }
 
     // Attempts to append a chunk to the generation queue.
@@ -198,14 +226,15 @@ impl World {
             },
             None => {
     
-                if self.chunk_queue.contains(at) { return true; }
+                if self.chunk_queue.contains(at) || self.workers.generating.contains_key(at) { return true; }
 
                 let fat =  Vector3::new(at.x as f32 + 0.5, at.y as f32 + 0.5, at.z as f32 + 0.5);
                 let dist = fat.distance(*player_pos);
     
                 if dist <= RENDER_DISTANCE as f32  {
 
-                    self.chunk_queue.push(*at);
+                    // Properly prioritized once the queue is next updated, see World::update_chunk_queue.
+                    self.chunk_queue.push(*at, dist);
                     true
                 }
                 else {
@@ -229,24 +258,215 @@ impl World {
 
     pub fn get_block_at(&self, position: Vector3<i32>) -> Option<&Block> {
 
-        let cs = chunk::CHUNK_SIZE as i32;
-        let csf = chunk::CHUNK_SIZE as f32;
-
-        let chunk_position = Vector3::new(position.x as f32, position.y as f32, position.z as f32) / csf;
-        let chunk_position = Vector3::new(chunk_position.x.floor() as i32, chunk_position.y.floor() as i32, chunk_position.z.floor() as i32);
-
-        let block_position = (position % cs + Vector3::new(cs, cs, cs)) % cs;
-        // ^ modulo, as '%' is actually remainder.
+        let (chunk_position, block_position) = Chunk::locate(position);
 
         match self.chunks.get(&chunk_position) {
             Some(chunk) => {
-                let id = chunk.grid[block_position.x as usize][block_position.y as usize][block_position.z as usize];
-                self.block_list.blocks.get(id as usize)
+                let state = chunk.grid[block_position.x][block_position.y][block_position.z];
+                self.block_list.get_block_from_state(state)
             },
             None => None
         }
     
     }
+
+    pub fn get_state_at(&self, position: Vector3<i32>) -> Option<u32> {
+        let (chunk_position, block_position) = Chunk::locate(position);
+        let chunk = self.chunks.get(&chunk_position)?;
+        Some(chunk.grid[block_position.x][block_position.y][block_position.z])
+    }
+
+    // Replaces the block at a position with the given state, relighting and remeshing around it.
+    // Returns false if the chunk it's in isn't loaded.
+    pub fn set_block_at(&mut self, position: Vector3<i32>, state: u32) -> bool {
+
+        let (chunk_position, block_position) = Chunk::locate(position);
+
+        let old_state = match self.chunks.get_mut(&chunk_position) {
+            Some(chunk) => {
+                let old_state = chunk.grid[block_position.x][block_position.y][block_position.z];
+                chunk.grid[block_position.x][block_position.y][block_position.z] = state;
+                old_state
+            },
+            None => return false,
+        };
+
+        // Faces and occlusion of the surrounding blocks may have changed.
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    let (at, _) = Chunk::locate(position + Vector3::new(x, y, z));
+                    if let Some(chunk) = self.chunks.get_mut(&at) {
+                        chunk.should_regen_mesh = true;
+                    }
+                }
+            }
+        }
+
+        self.update_light_at(position, old_state, state);
+        true
+    }
     
-    
+    // The y of the highest solid block in a column, looking from 'top' down to 'bottom'. Air and fluids are looked through.
+    pub fn get_highest_block(&self, x: i32, z: i32, top: i32, bottom: i32) -> Option<i32> {
+
+        let mut y = top;
+        while y >= bottom {
+            let (chunk_position, local) = Chunk::locate(Vector3::new(x, y, z));
+
+            // Unloaded chunks are skipped in one go.
+            let chunk = match self.chunks.get(&chunk_position) {
+                Some(chunk) => chunk,
+                None => {
+                    y -= local.y as i32 + 1;
+                    continue;
+                }
+            };
+
+            for ly in (0..=local.y).rev() {
+                if y < bottom { return None; }
+                let state = chunk.grid[local.x][ly][local.z];
+                if self.block_list.get_block_from_state(state).is_some_and(|block| block.is_solid()) {
+                    return Some(y);
+                }
+                y -= 1;
+            }
+        }
+
+        None
+    }
+
+    // Which cells of the cloud map are filled, row by row along x.
+    // Noise is blended with copies offset by the map's size so the map tiles without seams.
+    pub fn generate_cloud_map(&self) -> Vec<bool> {
+
+        let mut noise = FastNoise::seeded(self.seed.wrapping_add(3));
+        noise.set_noise_type(NoiseType::SimplexFractal);
+        noise.set_fractal_octaves(3);
+        noise.set_frequency(0.12);
+
+        let size = CLOUD_MAP_SIZE as f32;
+        let mut map = Vec::with_capacity(CLOUD_MAP_SIZE * CLOUD_MAP_SIZE);
+
+        for x in 0..CLOUD_MAP_SIZE {
+            for z in 0..CLOUD_MAP_SIZE {
+                let (fx, fz) = (x as f32, z as f32);
+                let (tx, tz) = (fx / size, fz / size);
+
+                let value = noise.get_noise(fx, fz) * (1.0 - tx) * (1.0 - tz)
+                    + noise.get_noise(fx - size, fz) * tx * (1.0 - tz)
+                    + noise.get_noise(fx, fz - size) * (1.0 - tx) * tz
+                    + noise.get_noise(fx - size, fz - size) * tx * tz;
+
+                map.push(value > 0.05);
+            }
+        }
+
+        map
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::{*, lighting::LightChannel};
+
+    // Making a world writes out the block atlas and reads it back, so only one gets made at a time.
+    static CREATING: Mutex<()> = Mutex::new(());
+
+    // A world with no chunks loaded, for tests to put them in by hand.
+    pub(super) fn empty_world() -> World {
+        let _creating = CREATING.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        World::new()
+    }
+
+    // Puts an unlit chunk of nothing but air into the world.
+    pub(super) fn insert_empty_chunk(world: &mut World, position: Vector3<i32>) {
+        let mut chunk = Chunk::new(position, &FastNoise::new(), &world.block_list);
+        chunk.grid = Box::new([[[0; CHUNK_SIZE]; CHUNK_SIZE]; CHUNK_SIZE]);
+        world.chunks.insert(position, chunk);
+    }
+
+    // The eight chunks around the corner at the origin.
+    fn corner_chunks() -> Vec<Vector3<i32>> {
+        let mut chunks = Vec::new();
+        for x in -1..=0 {
+            for y in -1..=0 {
+                for z in -1..=0 {
+                    chunks.push(Vector3::new(x, y, z));
+                }
+            }
+        }
+        chunks
+    }
+
+    fn clear_regen_flags(world: &mut World) {
+        for chunk in world.chunks.values_mut() {
+            chunk.should_regen_mesh = false;
+        }
+    }
+
+    #[test]
+    fn placing_and_removing_a_light_at_a_chunk_corner() {
+
+        let mut world = empty_world();
+        for position in corner_chunks() {
+            insert_empty_chunk(&mut world, position);
+        }
+        let glowstone = world.block_list.get_block("glowstone").unwrap().default_state;
+        let corner = Vector3::new(0, 0, 0);
+
+        clear_regen_flags(&mut world);
+        assert!(world.set_block_at(corner, glowstone));
+
+        for position in corner_chunks() {
+            // The block of each chunk right against the corner.
+            let nearest = corner + position;
+            let red = world.get_light_at(nearest, LightChannel::Red).unwrap();
+            assert!(red > 0, "no light reached chunk {:?}", position);
+            assert!(world.chunks[&position].should_regen_mesh, "chunk {:?} wasn't remeshed after placing", position);
+        }
+        assert_eq!(world.get_light_at(corner, LightChannel::Red), Some(15));
+
+        clear_regen_flags(&mut world);
+        assert!(world.set_block_at(corner, 0));
+
+        for position in corner_chunks() {
+            let chunk = &world.chunks[&position];
+            for x in 0..CHUNK_SIZE {
+                for y in 0..CHUNK_SIZE {
+                    for z in 0..CHUNK_SIZE {
+                        assert_eq!(chunk.get_block_light(x, y, z), [0, 0, 0], "light left in chunk {:?} at {} {} {}", position, x, y, z);
+                    }
+                }
+            }
+            assert!(chunk.should_regen_mesh, "chunk {:?} wasn't remeshed after removing", position);
+        }
+    }
+
+    #[test]
+    fn highest_block_looks_through_fluids() {
+
+        let mut world = empty_world();
+        insert_empty_chunk(&mut world, Vector3::new(0, 0, 0));
+        insert_empty_chunk(&mut world, Vector3::new(0, 1, 0));
+        let stone = world.block_list.get_block("stone").unwrap().default_state;
+        let water = world.block_list.get_block("water").unwrap().default_state;
+
+        assert!(world.set_block_at(Vector3::new(3, 2, 3), stone));
+        for y in 3..20 {
+            assert!(world.set_block_at(Vector3::new(3, y, 3), water));
+        }
+        assert_eq!(world.get_highest_block(3, 3, 31, 0), Some(2));
+        assert_eq!(world.get_highest_block(3, 3, 1, 0), None);
+        assert_eq!(world.get_highest_block(4, 3, 31, 0), None);
+    }
+
+    #[test]
+    fn setting_a_block_in_an_unloaded_chunk() {
+        let mut world = empty_world();
+        assert!(!world.set_block_at(Vector3::new(0, 0, 0), 0));
+    }
 }
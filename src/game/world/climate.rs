@@ -0,0 +1,79 @@
+use std::cmp::min;
+
+use bracket_noise::prelude::*;
+use cgmath::{Vector3, num_traits::clamp};
+use image::{DynamicImage, GenericImageView};
+
+use crate::game::{chunk::CHUNK_SIZE, color::Color};
+
+// Temperature and humidity across the world, and the tints they give.
+// Shared with the workers, which sample tints for whatever they mesh.
+pub struct Climate {
+    pub temperature_noise: FastNoise,
+    pub humidity_noise: FastNoise,
+    pub foliage_colormap: DynamicImage,
+}
+
+impl Climate {
+
+    pub fn new(seed: u64) -> Climate {
+
+        let mut temperature_noise = FastNoise::seeded(seed.wrapping_add(1));
+        temperature_noise.set_noise_type(NoiseType::Simplex);
+        temperature_noise.set_frequency(0.004);
+
+        let mut humidity_noise = FastNoise::seeded(seed.wrapping_add(2));
+        humidity_noise.set_noise_type(NoiseType::Simplex);
+        humidity_noise.set_frequency(0.004);
+
+        Climate {
+            temperature_noise,
+            humidity_noise,
+            foliage_colormap: image::open("./src/game/data/colormaps/foliage.png").unwrap(),
+        }
+    }
+
+    // Returns the temperature and humidity of a column, both 0..1.
+    pub fn get_climate(&self, x: i32, z: i32) -> (f32, f32) {
+        let temperature = self.temperature_noise.get_noise(x as f32, z as f32) * 0.5 + 0.5;
+        let humidity = self.humidity_noise.get_noise(x as f32, z as f32) * 0.5 + 0.5;
+        (clamp(temperature, 0.0, 1.0), clamp(humidity, 0.0, 1.0))
+    }
+
+    // Returns the color that tinted block faces get in a column, looked up from the foliage colormap
+    // by temperature along x and humidity along y.
+    pub fn get_tint(&self, x: i32, z: i32) -> Color {
+
+        let (temperature, humidity) = self.get_climate(x, z);
+        let (width, height) = self.foliage_colormap.dimensions();
+
+        let fx = temperature * (width - 1) as f32;
+        let fy = humidity * (height - 1) as f32;
+        let (x0, y0) = (fx.floor() as u32, fy.floor() as u32);
+        let (x1, y1) = (min(x0 + 1, width - 1), min(y0 + 1, height - 1));
+
+        let sample = |x: u32, y: u32| {
+            let p = self.foliage_colormap.get_pixel(x, y);
+            Color::from_u32(p[0] as u32, p[1] as u32, p[2] as u32)
+        };
+
+        let top = Color::lerp(sample(x0, y0), sample(x1, y0), fx.fract());
+        let bottom = Color::lerp(sample(x0, y1), sample(x1, y1), fx.fract());
+        Color::lerp(top, bottom, fy.fract())
+    }
+
+    // The tint of every column of a chunk, or with a 'scale' above 1 of a far off region, sampled at the blocks
+    // it's generated from, see Chunk::generate_grid.
+    pub fn get_tints(&self, position: Vector3<i32>, scale: i32) -> [[Color; CHUNK_SIZE]; CHUNK_SIZE] {
+
+        let mut tints = [[Color::from_rgb(1.0, 1.0, 1.0); CHUNK_SIZE]; CHUNK_SIZE];
+        for (x, column) in tints.iter_mut().enumerate() {
+            for (z, tint) in column.iter_mut().enumerate() {
+                let block_x = (position.x * CHUNK_SIZE as i32 + x as i32) * scale;
+                let block_z = (position.z * CHUNK_SIZE as i32 + z as i32) * scale;
+                *tint = self.get_tint(block_x, block_z);
+            }
+        }
+        tints
+    }
+}
@@ -0,0 +1,382 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use cgmath::Vector3;
+
+use super::World;
+use crate::game::chunk::{Chunk, CHUNK_SIZE, TERRAIN_TOP};
+
+pub const MAX_LIGHT: u8 = 15;
+
+const DIRECTIONS: [Vector3<i32>; 6] = [Chunk::LEFT, Chunk::RIGHT, Chunk::DOWN, Chunk::UP, Chunk::BACKWARD, Chunk::FORWARD];
+
+// Whether a chunk at this height starts above the highest terrain, so it's open air even if it isn't loaded.
+fn is_above_terrain(chunk_y: i32) -> bool {
+    chunk_y * CHUNK_SIZE as i32 > TERRAIN_TOP
+}
+
+// Sky light comes from above, block light from emitting blocks as separate red, green and blue
+// levels. Every channel spreads the same way and is stored side by side, but they never mix.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum LightChannel {
+    Sky,
+    Red,
+    Green,
+    Blue,
+}
+
+impl LightChannel {
+
+    pub const BLOCK: [LightChannel; 3] = [LightChannel::Red, LightChannel::Green, LightChannel::Blue];
+
+    fn get(&self, chunk: &Chunk, x: usize, y: usize, z: usize) -> u8 {
+        match self {
+            LightChannel::Sky => chunk.get_sky_light(x, y, z),
+            LightChannel::Red => chunk.get_block_light(x, y, z)[0],
+            LightChannel::Green => chunk.get_block_light(x, y, z)[1],
+            LightChannel::Blue => chunk.get_block_light(x, y, z)[2],
+        }
+    }
+
+    fn set(&self, chunk: &mut Chunk, x: usize, y: usize, z: usize, level: u8) {
+        match self {
+            LightChannel::Sky => chunk.set_sky_light(x, y, z, level),
+            LightChannel::Red => chunk.set_block_light(x, y, z, 0, level),
+            LightChannel::Green => chunk.set_block_light(x, y, z, 1, level),
+            LightChannel::Blue => chunk.set_block_light(x, y, z, 2, level),
+        }
+    }
+
+    // The level of this channel in an emitter's color.
+    fn emission(&self, emission: [u8; 3]) -> u8 {
+        match self {
+            LightChannel::Sky => 0,
+            LightChannel::Red => emission[0],
+            LightChannel::Green => emission[1],
+            LightChannel::Blue => emission[2],
+        }
+    }
+
+    // Sky light travels straight down without fading, everywhere else light loses a level per block.
+    fn next_level(&self, level: u8, direction: Vector3<i32>) -> u8 {
+        if *self == LightChannel::Sky && level == MAX_LIGHT && direction == Chunk::DOWN { MAX_LIGHT } else { level.saturating_sub(1) }
+    }
+}
+
+// Returns the chunks whose meshes sample a block, which is its own chunk and whichever
+// neighbors it borders, including diagonal ones since smooth lighting samples corners.
+fn affected_chunks(chunk_position: Vector3<i32>, local: Vector3<usize>) -> Vec<Vector3<i32>> {
+
+    let max = CHUNK_SIZE - 1;
+    let offsets = |l: usize| -> Vec<i32> {
+        if l == 0 { vec![0, -1] } else if l == max { vec![0, 1] } else { vec![0] }
+    };
+
+    let mut chunks = Vec::new();
+    for x in offsets(local.x) {
+        for y in offsets(local.y) {
+            for z in offsets(local.z) {
+                chunks.push(chunk_position + Vector3::new(x, y, z));
+            }
+        }
+    }
+
+    chunks
+}
+
+impl World {
+
+    pub fn get_light_at(&self, position: Vector3<i32>, channel: LightChannel) -> Option<u8> {
+        let (chunk_position, local) = Chunk::locate(position);
+        let chunk = self.chunks.get(&chunk_position)?;
+        Some(channel.get(chunk, local.x, local.y, local.z))
+    }
+
+    fn set_light_at(&mut self, position: Vector3<i32>, channel: LightChannel, level: u8) {
+
+        let (chunk_position, local) = Chunk::locate(position);
+        match self.chunks.get_mut(&chunk_position) {
+            Some(chunk) => channel.set(chunk, local.x, local.y, local.z, level),
+            None => return,
+        }
+
+        for at in affected_chunks(chunk_position, local) {
+            if let Some(chunk) = self.chunks.get_mut(&at) {
+                chunk.should_regen_mesh = true;
+            }
+        }
+    }
+
+    // Lights a chunk that was just inserted, pulling light in from its loaded neighbors
+    // and letting its own light spread back out into them.
+    pub fn light_chunk(&mut self, at: Vector3<i32>) {
+
+        let cs = CHUNK_SIZE as i32;
+        let mut sky_seeds: Vec<(Vector3<i32>, u8)> = Vec::new();
+        let mut block_seeds: [Vec<(Vector3<i32>, u8)>; 3] = [Vec::new(), Vec::new(), Vec::new()];
+
+        // The top of a column's loaded chunks sees the sky when the chunk above it would be nothing but air.
+        if !self.chunks.contains_key(&(at + Chunk::UP)) && is_above_terrain(at.y + 1) {
+            for x in 0..cs {
+                for z in 0..cs {
+                    sky_seeds.push((at * cs + Vector3::new(x, cs - 1, z), MAX_LIGHT));
+                }
+            }
+        }
+
+        // Emitting blocks light themselves, even opaque ones.
+        if let Some(chunk) = self.chunks.get_mut(&at) {
+            for x in 0..CHUNK_SIZE {
+                for y in 0..CHUNK_SIZE {
+                    for z in 0..CHUNK_SIZE {
+                        let emission = self.block_list.get_emission(chunk.grid[x][y][z]);
+                        for (i, level) in emission.iter().enumerate() {
+                            if *level > 0 {
+                                chunk.set_block_light(x, y, z, i, *level);
+                                block_seeds[i].push((at * cs + Vector3::new(x as i32, y as i32, z as i32), *level));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // The bordering layer of every loaded neighbor.
+        for dir in DIRECTIONS.iter() {
+
+            let neighbor = match self.chunks.get(&(at + dir)) {
+                Some(chunk) => chunk,
+                None => continue,
+            };
+
+            // Local coordinate on the axis facing us, 'free' on the other two.
+            let pick = |d: i32, free: i32| if d < 0 { cs - 1 } else if d > 0 { 0 } else { free };
+
+            for a in 0..cs {
+                for b in 0..cs {
+
+                    let local = if dir.x != 0 { Vector3::new(pick(dir.x, 0), a, b) }
+                        else if dir.y != 0 { Vector3::new(a, pick(dir.y, 0), b) }
+                        else { Vector3::new(a, b, pick(dir.z, 0)) };
+
+                    let (x, y, z) = (local.x as usize, local.y as usize, local.z as usize);
+
+                    let level = neighbor.get_sky_light(x, y, z);
+                    if level > 1 {
+                        sky_seeds.push(((at + dir) * cs + local, level));
+                    }
+
+                    for (i, level) in neighbor.get_block_light(x, y, z).iter().enumerate() {
+                        if *level > 1 {
+                            block_seeds[i].push(((at + dir) * cs + local, *level));
+                        }
+                    }
+                }
+            }
+        }
+
+        self.spread_light(LightChannel::Sky, sky_seeds);
+        for (channel, seeds) in LightChannel::BLOCK.iter().zip(block_seeds.iter_mut()) {
+            self.spread_light(*channel, std::mem::take(seeds));
+        }
+    }
+
+    // Fixes up light after the block at a position was changed.
+    pub fn update_light_at(&mut self, position: Vector3<i32>, old_state: u32, new_state: u32) {
+
+        let was_opaque = self.block_list.is_opaque(old_state);
+        let is_opaque = self.block_list.is_opaque(new_state);
+        let old_emission = self.block_list.get_emission(old_state);
+        let new_emission = self.block_list.get_emission(new_state);
+
+        // Light that was passing through, or was given off by the old block, goes away first.
+        if is_opaque && !was_opaque {
+            let level = self.get_light_at(position, LightChannel::Sky).unwrap_or(0);
+            self.set_light_at(position, LightChannel::Sky, 0);
+            self.remove_light(LightChannel::Sky, position, level);
+        }
+
+        if (is_opaque && !was_opaque) || old_emission != [0, 0, 0] {
+            for channel in LightChannel::BLOCK.iter() {
+                let level = self.get_light_at(position, *channel).unwrap_or(0);
+                self.set_light_at(position, *channel, 0);
+                self.remove_light(*channel, position, level);
+            }
+        }
+
+        // Then light can flow back in from around it.
+        if was_opaque && !is_opaque {
+            for channel in [LightChannel::Sky, LightChannel::Red, LightChannel::Green, LightChannel::Blue].iter() {
+                let mut seeds = Vec::new();
+                for dir in DIRECTIONS.iter() {
+                    if let Some(level) = self.get_light_at(position + dir, *channel) {
+                        seeds.push((position + dir, level));
+                    }
+                }
+                self.spread_light(*channel, seeds);
+            }
+        }
+
+        for channel in LightChannel::BLOCK.iter() {
+            let level = channel.emission(new_emission);
+            if level > 0 {
+                self.set_light_at(position, *channel, level);
+                self.spread_light(*channel, vec![(position, level)]);
+            }
+        }
+    }
+
+    // Spreads light out from the given block positions, each of which is raised to at least
+    // its level if it's not opaque. Works a chunk at a time, handing off to neighbors at the borders.
+    fn spread_light(&mut self, channel: LightChannel, seeds: Vec<(Vector3<i32>, u8)>) {
+
+        let cs = CHUNK_SIZE as i32;
+        let mut pending: HashMap<Vector3<i32>, VecDeque<(Vector3<i32>, u8)>> = HashMap::new();
+        let mut changed: HashSet<Vector3<i32>> = HashSet::new();
+
+        for (position, level) in seeds {
+            let (chunk_position, local) = Chunk::locate(position);
+            let local = Vector3::new(local.x as i32, local.y as i32, local.z as i32);
+            pending.entry(chunk_position).or_default().push_back((local, level));
+        }
+
+        while let Some(chunk_position) = pending.keys().next().cloned() {
+
+            let mut queue = pending.remove(&chunk_position).unwrap();
+            let block_list = &self.block_list;
+            let chunk = match self.chunks.get_mut(&chunk_position) {
+                Some(chunk) => chunk,
+                None => continue,
+            };
+
+            while let Some((p, level)) = queue.pop_front() {
+
+                let (x, y, z) = (p.x as usize, p.y as usize, p.z as usize);
+                let current = channel.get(chunk, x, y, z);
+
+                if current < level {
+                    if block_list.is_opaque(chunk.grid[x][y][z]) { continue; }
+                    channel.set(chunk, x, y, z, level);
+                    changed.extend(affected_chunks(chunk_position, Vector3::new(x, y, z)));
+                }
+                else if current > level {
+                    continue;
+                }
+
+                for dir in DIRECTIONS.iter() {
+
+                    let level = channel.next_level(level, *dir);
+                    if level == 0 { continue; }
+
+                    let n = p + dir;
+                    if n.x >= 0 && n.x < cs && n.y >= 0 && n.y < cs && n.z >= 0 && n.z < cs {
+                        if channel.get(chunk, n.x as usize, n.y as usize, n.z as usize) < level {
+                            queue.push_back((n, level));
+                        }
+                    }
+                    else {
+                        let local = Vector3::new(n.x.rem_euclid(cs), n.y.rem_euclid(cs), n.z.rem_euclid(cs));
+                        pending.entry(chunk_position + dir).or_default().push_back((local, level));
+                    }
+                }
+            }
+        }
+
+        for at in changed {
+            if let Some(chunk) = self.chunks.get_mut(&at) {
+                chunk.should_regen_mesh = true;
+            }
+        }
+    }
+
+    // Darkens everything that was lit through a block which has just lost the given level,
+    // then relights the area from whatever light is left around it.
+    fn remove_light(&mut self, channel: LightChannel, position: Vector3<i32>, level: u8) {
+
+        let mut queue: VecDeque<(Vector3<i32>, u8)> = VecDeque::new();
+        let mut relight: Vec<Vector3<i32>> = Vec::new();
+        queue.push_back((position, level));
+
+        while let Some((p, level)) = queue.pop_front() {
+
+            for dir in DIRECTIONS.iter() {
+
+                let n = p + dir;
+                let neighbor_level = match self.get_light_at(n, channel) {
+                    Some(l) => l,
+                    None => continue,
+                };
+
+                // Anything dimmer, or directly under a sky column, was lit by us.
+                if neighbor_level != 0 && (neighbor_level < level || (neighbor_level == MAX_LIGHT && channel.next_level(level, *dir) == MAX_LIGHT)) {
+
+                    self.set_light_at(n, channel, 0);
+                    queue.push_back((n, neighbor_level));
+
+                    // Emitters are their own source, they keep their light and shine back in.
+                    if channel != LightChannel::Sky {
+                        let emission = self.get_state_at(n).map_or(0, |state| channel.emission(self.block_list.get_emission(state)));
+                        if emission > 0 {
+                            self.set_light_at(n, channel, emission);
+                            relight.push(n);
+                        }
+                    }
+                }
+                else if neighbor_level >= level && neighbor_level != 0 {
+                    relight.push(n);
+                }
+            }
+        }
+
+        // Some of those may have been darkened since, so spread whatever they hold now.
+        let relight = relight.into_iter()
+            .filter_map(|p| self.get_light_at(p, channel).map(|level| (p, level)))
+            .filter(|(_, level)| *level > 0)
+            .collect();
+
+        self.spread_light(channel, relight);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::tests::{empty_world, insert_empty_chunk};
+
+    fn sky_light_at_top(world: &World, chunk: Vector3<i32>) -> u8 {
+        let top = chunk * CHUNK_SIZE as i32 + Vector3::new(0, CHUNK_SIZE as i32 - 1, 0);
+        world.get_light_at(top, LightChannel::Sky).unwrap()
+    }
+
+    #[test]
+    fn top_of_a_column_above_terrain_sees_the_sky() {
+
+        // The highest chunk terrain can reach into, and the one below it.
+        let open = TERRAIN_TOP / CHUNK_SIZE as i32 + 1;
+        let (highest, below) = (Vector3::new(0, open - 1, 0), Vector3::new(0, open - 2, 0));
+
+        let mut world = empty_world();
+        insert_empty_chunk(&mut world, below);
+        world.light_chunk(below);
+        assert_eq!(sky_light_at_top(&world, below), 0, "terrain may be above, so it's left dark");
+
+        // With open air above, the new top of the column is lit, and the light falls through into the chunk under it.
+        insert_empty_chunk(&mut world, highest);
+        world.light_chunk(highest);
+        assert_eq!(sky_light_at_top(&world, highest), MAX_LIGHT);
+        assert_eq!(world.get_light_at(below * CHUNK_SIZE as i32, LightChannel::Sky), Some(MAX_LIGHT));
+    }
+
+    #[test]
+    fn chunk_under_a_loaded_one_takes_its_light() {
+
+        let open = TERRAIN_TOP / CHUNK_SIZE as i32 + 1;
+        let (above, under) = (Vector3::new(0, open + 1, 0), Vector3::new(0, open, 0));
+
+        let mut world = empty_world();
+        insert_empty_chunk(&mut world, above);
+        world.light_chunk(above);
+        insert_empty_chunk(&mut world, under);
+        world.light_chunk(under);
+        assert_eq!(world.get_light_at(under * CHUNK_SIZE as i32, LightChannel::Sky), Some(MAX_LIGHT));
+    }
+}
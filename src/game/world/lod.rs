@@ -0,0 +1,276 @@
+use std::collections::HashSet;
+
+use cgmath::{MetricSpace, Vector3};
+
+use super::{World, workers::Job};
+use crate::game::{CHUNKS_MESHED_PER_FRAME, RENDER_DISTANCE, camera::Camera, chunk::{CHUNK_SIZE, TERRAIN_BOTTOM, TERRAIN_TOP}, profiler::{self, Phase}, renderer::{Renderer, arena::ChunkMesh, vertex::ChunkInstance}};
+
+// Levels of detail past full detail, each a region twice as many chunks across as the last,
+// drawn with one block for every 2, 4 and 8 blocks on each axis.
+pub const LOD_LEVELS: usize = 3;
+
+// How many regions can be waiting on the workers at once.
+const MAX_LOD_GENERATING: usize = 32;
+
+// A far off region of terrain, 2^level chunks across on each axis, meshed at 16 blocks across just like a chunk.
+pub struct LodRegion {
+    pub level: u32,
+    pub position: Vector3<i32>,
+    pub mesh: Option<ChunkMesh>,
+    pub translucent_mesh: Option<ChunkMesh>,
+}
+
+impl LodRegion {
+
+    // How many chunks across it is.
+    pub fn size(level: u32) -> i32 {
+        1 << level
+    }
+
+    // Its center, in chunks.
+    pub fn center(level: u32, position: Vector3<i32>) -> Vector3<f32> {
+        Vector3::new(position.x as f32 + 0.5, position.y as f32 + 0.5, position.z as f32 + 0.5) * LodRegion::size(level) as f32
+    }
+
+    // Radius of a sphere around it, in blocks, for frustum culling.
+    pub fn radius(level: u32) -> f32 {
+        f32::sqrt(3.0) * (LodRegion::size(level) * CHUNK_SIZE as i32) as f32 / 2.0
+    }
+
+    // The chunks it covers, from the first up to but not including the last.
+    pub fn chunk_bounds(level: u32, position: Vector3<i32>) -> (Vector3<i32>, Vector3<i32>) {
+        let size = LodRegion::size(level);
+        (position * size, (position + Vector3::new(1, 1, 1)) * size)
+    }
+
+    pub fn overlaps(a: (u32, Vector3<i32>), b: (u32, Vector3<i32>)) -> bool {
+        let (a_min, a_max) = LodRegion::chunk_bounds(a.0, a.1);
+        let (b_min, b_max) = LodRegion::chunk_bounds(b.0, b.1);
+        a_min.x < b_max.x && b_min.x < a_max.x && a_min.y < b_max.y && b_min.y < a_max.y && a_min.z < b_max.z && b_min.z < a_max.z
+    }
+}
+
+impl World {
+
+    // How far terrain is drawn at all, in chunks.
+    pub fn view_distance(&self) -> f32 {
+        self.lod_distances[LOD_LEVELS - 1]
+    }
+
+    // Regions of a level closer than this are split into smaller ones, in chunks.
+    // Full detail chunks stop a chunk short of where they're loaded, so the ones at the edge have time to load.
+    fn split_distance(&self, level: u32) -> f32 {
+        if level == 1 { RENDER_DISTANCE as f32 - 1.0 } else { self.lod_distances[level as usize - 2] }
+    }
+
+    // Whether a chunk is drawn at full detail rather than as part of a region.
+    pub fn is_full_detail(&self, position: Vector3<i32>, camera: &Camera) -> bool {
+        let parent = Vector3::new(position.x.div_euclid(2), position.y.div_euclid(2), position.z.div_euclid(2));
+        LodRegion::center(1, parent).distance(camera.position / CHUNK_SIZE as f32) < self.split_distance(1)
+    }
+
+    // Picks which regions to draw around the camera, as (level, position).
+    // Starting from the biggest, a region is split into the eight a level below it whenever it's closer than that level reaches,
+    // down to full detail chunks, which are left to the world. That way levels never overlap or leave gaps between them.
+    pub fn select_lod_regions(&self, camera: &Camera) -> Vec<(u32, Vector3<i32>)> {
+
+        let camera_chunk = camera.position / CHUNK_SIZE as f32;
+        let top = LOD_LEVELS as u32;
+        let size = LodRegion::size(top) as f32;
+        let reach = (self.view_distance() / size).ceil() as i32 + 1;
+        let around = Vector3::new((camera_chunk.x / size).floor() as i32, (camera_chunk.y / size).floor() as i32, (camera_chunk.z / size).floor() as i32);
+
+        let mut selected = Vec::new();
+        let mut stack = Vec::new();
+        for x in -reach..=reach {
+            for y in -reach..=reach {
+                for z in -reach..=reach {
+                    let position = around + Vector3::new(x, y, z);
+                    if LodRegion::center(top, position).distance(camera_chunk) < self.view_distance() {
+                        stack.push((top, position));
+                    }
+                }
+            }
+        }
+
+        while let Some((level, position)) = stack.pop() {
+
+            // Regions all air or all buried in stone have nothing worth drawing.
+            let block_size = LodRegion::size(level) * CHUNK_SIZE as i32;
+            if position.y * block_size > TERRAIN_TOP || (position.y + 1) * block_size <= TERRAIN_BOTTOM { continue; }
+
+            if LodRegion::center(level, position).distance(camera_chunk) >= self.split_distance(level) {
+                selected.push((level, position));
+                continue;
+            }
+
+            if level == 1 { continue; }
+            for x in 0..2 {
+                for y in 0..2 {
+                    for z in 0..2 {
+                        stack.push((level - 1, position * 2 + Vector3::new(x, y, z)));
+                    }
+                }
+            }
+        }
+
+        selected
+    }
+
+    // Requests the regions that should be drawn, closest first, and applies whatever the workers have finished.
+    // Regions no longer wanted are kept around until whatever replaces them is ready, so nothing pops out in between.
+    pub fn update_lod(&mut self, renderer: &mut Renderer, camera: &Camera) {
+
+        let lod = profiler::scope(Phase::Lod);
+        let camera_chunk = camera.position / CHUNK_SIZE as f32;
+        let mut selected = self.select_lod_regions(camera);
+        selected.sort_by(|a, b| {
+            let a = LodRegion::center(a.0, a.1).distance(camera_chunk);
+            let b = LodRegion::center(b.0, b.1).distance(camera_chunk);
+            a.total_cmp(&b)
+        });
+
+        for (level, position) in selected.iter() {
+            if self.workers.lod_generating.len() >= MAX_LOD_GENERATING { break; }
+            let key = (*level, *position);
+            if self.lod_regions.contains_key(&key) || self.workers.lod_generating.contains(&key) { continue; }
+
+            self.workers.lod_generating.insert(key);
+            self.workers.jobs.send(Job::Lod(*level, *position)).unwrap();
+        }
+
+        drop(lod);
+
+        let uploads = profiler::scope(Phase::Uploads);
+        for (level, position, parts) in self.workers.lod_meshed.try_iter().take(CHUNKS_MESHED_PER_FRAME as usize) {
+
+            self.workers.lod_generating.remove(&(level, position));
+
+            let size = LodRegion::size(level);
+            // Blocks span one less on z than their place in the grid, which a bigger block has to be moved over to match.
+            let mut origin = (position * size * CHUNK_SIZE as i32).cast::<f32>().unwrap();
+            origin.z += (size - 1) as f32;
+            let instance = ChunkInstance { origin: origin.into(), scale: size as f32 };
+
+            let arena = &mut renderer.chunk_arena;
+            let mesh = if parts.vertices.is_empty() { None }
+                else { Some(arena.upload(&renderer.device, &renderer.queue, &parts.vertices, instance)) };
+            let translucent_mesh = if parts.translucent_vertices.is_empty() { None }
+                else { Some(arena.upload(&renderer.device, &renderer.queue, &parts.translucent_vertices, instance)) };
+
+            self.lod_regions.insert((level, position), LodRegion { level, position, mesh, translucent_mesh });
+        }
+
+        drop(uploads);
+
+        let _lod = profiler::scope(Phase::Lod);
+        let waiting: Vec<(u32, Vector3<i32>)> = selected.iter().cloned().filter(|key| !self.lod_regions.contains_key(key)).collect();
+        let selected: HashSet<(u32, Vector3<i32>)> = selected.into_iter().collect();
+
+        let stale: Vec<(u32, Vector3<i32>)> = self.lod_regions.keys().cloned().filter(|key| !selected.contains(key)).collect();
+        for key in stale {
+
+            let out_of_view = LodRegion::center(key.0, key.1).distance(camera_chunk) > self.view_distance();
+            let replacing = waiting.iter().any(|other| LodRegion::overlaps(key, *other));
+
+            // Or where it's been replaced by full detail chunks that haven't been meshed yet.
+            let (min, max) = LodRegion::chunk_bounds(key.0, key.1);
+            let unmeshed = (min.x..max.x).any(|x| (min.y..max.y).any(|y| (min.z..max.z).any(|z| {
+                let position = Vector3::new(x, y, z);
+                self.is_full_detail(position, camera) && !self.chunks.get(&position).is_some_and(|chunk| chunk.meshed)
+            })));
+
+            if out_of_view || !(replacing || unmeshed) {
+                self.lod_regions.remove(&key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use super::super::tests::empty_world;
+    use crate::game::camera::frustum::Frustum;
+
+    fn camera_at(position: Vector3<f32>) -> Camera {
+        Camera {
+            position,
+            yaw: 0.0,
+            pitch: 0.0,
+            speed: 0.0,
+            sensitivity: 0.0,
+            fovy: cgmath::Deg(90.0).into(),
+            near: 0.1,
+            far: 1000.0,
+            width: 1,
+            height: 1,
+            frustum: Frustum::new(),
+        }
+    }
+
+    // Every chunk within reach of the camera has to be drawn exactly once, either at full detail or by one region.
+    // Chunks near the edge of the view may be left out, as the biggest regions are picked by their centers.
+    fn assert_covered_once(world: &World, camera: &Camera) {
+
+        let selected = world.select_lod_regions(camera);
+        let camera_chunk = camera.position / CHUNK_SIZE as f32;
+        let edge = world.view_distance() - LodRegion::radius(LOD_LEVELS as u32) / CHUNK_SIZE as f32;
+        let reach = world.view_distance().ceil() as i32;
+        let around = Vector3::new(camera_chunk.x.floor() as i32, 0, camera_chunk.z.floor() as i32);
+
+        // Regions all air or all stone are skipped, so only chunks every level keeps are checked.
+        let bottom = TERRAIN_BOTTOM / CHUNK_SIZE as i32;
+        let top = TERRAIN_TOP / CHUNK_SIZE as i32 - 1;
+
+        // How many regions each chunk is part of.
+        let mut regions: HashMap<Vector3<i32>, usize> = HashMap::new();
+        for (level, position) in selected.iter() {
+            let (min, max) = LodRegion::chunk_bounds(*level, *position);
+            for x in min.x..max.x {
+                for y in min.y.max(bottom)..max.y.min(top + 1) {
+                    for z in min.z..max.z {
+                        *regions.entry(Vector3::new(x, y, z)).or_insert(0) += 1;
+                    }
+                }
+            }
+        }
+
+        for x in -reach..=reach {
+            for y in bottom..=top {
+                for z in -reach..=reach {
+
+                    let chunk = Vector3::new(around.x + x, y, around.z + z);
+                    let drawn = world.is_full_detail(chunk, camera) as usize + regions.get(&chunk).cloned().unwrap_or(0);
+
+                    let center = Vector3::new(chunk.x as f32 + 0.5, chunk.y as f32 + 0.5, chunk.z as f32 + 0.5);
+                    if center.distance(camera_chunk) < edge {
+                        assert_eq!(drawn, 1, "chunk {:?} drawn {} times", chunk, drawn);
+                    }
+                    else {
+                        assert!(drawn <= 1, "chunk {:?} drawn {} times", chunk, drawn);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn regions_leave_no_gaps_or_overlaps() {
+
+        let mut world = empty_world();
+        for position in [Vector3::new(0.0, 0.0, 0.0), Vector3::new(37.5, 20.0, -100.25), Vector3::new(-300.0, -40.0, 255.9)].iter() {
+            assert_covered_once(&world, &camera_at(*position));
+        }
+
+        // Levels set to reach further, with cameras as far out from the origin as each of them reaches.
+        world.lod_distances = [12.0, 20.0, 40.0];
+        for distance in world.lod_distances.iter() {
+            let offset = distance * CHUNK_SIZE as f32;
+            assert_covered_once(&world, &camera_at(Vector3::new(offset, 8.0, 0.0)));
+            assert_covered_once(&world, &camera_at(Vector3::new(-offset, 8.0, offset)));
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::{cmp::Ordering, collections::{BinaryHeap, HashSet}};
+
+use cgmath::{MetricSpace, Vector3};
+
+use super::World;
+use crate::game::{RENDER_DISTANCE, camera::Camera, chunk::CHUNK_SIZE};
+
+// Chunks in view count as this much closer than they are, so what's on screen fills in first.
+const IN_VIEW_PRIORITY_SCALE: f32 = 0.5;
+
+struct QueuedChunk {
+    // Lower is sooner.
+    priority: f32,
+    position: Vector3<i32>,
+}
+
+// BinaryHeap pops the largest first, so the order is reversed to pop the lowest priority instead.
+impl Ord for QueuedChunk {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.priority.total_cmp(&self.priority)
+    }
+}
+
+impl PartialOrd for QueuedChunk {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for QueuedChunk {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueuedChunk {}
+
+// Chunks waiting to be generated, closest to the player first.
+pub struct ChunkQueue {
+    heap: BinaryHeap<QueuedChunk>,
+    queued: HashSet<Vector3<i32>>,
+}
+
+impl ChunkQueue {
+
+    pub fn new() -> ChunkQueue {
+        ChunkQueue { heap: BinaryHeap::new(), queued: HashSet::new() }
+    }
+
+    // Returns false if the chunk was already queued.
+    pub fn push(&mut self, position: Vector3<i32>, priority: f32) -> bool {
+        if !self.queued.insert(position) { return false; }
+        self.heap.push(QueuedChunk { priority, position });
+        true
+    }
+
+    pub fn pop(&mut self) -> Option<Vector3<i32>> {
+        let chunk = self.heap.pop()?;
+        self.queued.remove(&chunk.position);
+        Some(chunk.position)
+    }
+
+    pub fn contains(&self, position: &Vector3<i32>) -> bool {
+        self.queued.contains(position)
+    }
+
+    // Gives every chunk a new priority, removing it from the queue if 'priority' returns None.
+    pub fn reprioritize<F: FnMut(Vector3<i32>) -> Option<f32>>(&mut self, mut priority: F) {
+
+        let queued = &mut self.queued;
+        let chunks: Vec<QueuedChunk> = self.heap.drain()
+            .filter_map(|chunk| match priority(chunk.position) {
+                Some(priority) => Some(QueuedChunk { priority, position: chunk.position }),
+                None => {
+                    queued.remove(&chunk.position);
+                    None
+                },
+            })
+            .collect();
+
+        // Building a heap from a Vec is linear, so this is cheap enough to do every frame.
+        self.heap = BinaryHeap::from(chunks);
+    }
+}
+
+impl World {
+
+    // How soon a chunk should be generated, its distance from the camera in chunks, less if it's in view.
+    // None if it's too far away to generate at all.
+    pub fn chunk_priority(position: Vector3<i32>, camera: &Camera) -> Option<f32> {
+
+        let center = Vector3::new(position.x as f32 + 0.5, position.y as f32 + 0.5, position.z as f32 + 0.5);
+        let distance = center.distance(camera.position / CHUNK_SIZE as f32);
+        if distance > RENDER_DISTANCE as f32 { return None; }
+
+        let radius = f32::sqrt(3.0) * CHUNK_SIZE as f32 / 2.0;
+        if camera.frustum.sphere_intersection(center * CHUNK_SIZE as f32, radius) {
+            Some(distance * IN_VIEW_PRIORITY_SCALE)
+        }
+        else {
+            Some(distance)
+        }
+    }
+
+    // Reorders the queue for where the camera is now, and cancels whatever it has moved away from,
+    // both chunks still queued and ones sent off to be generated that haven't been started yet.
+    pub fn update_chunk_queue(&mut self, camera: &Camera) {
+
+        self.chunk_queue.reprioritize(|position| World::chunk_priority(position, camera));
+        self.workers.cancel_generation(|position| World::chunk_priority(position, camera).is_none());
+    }
+}
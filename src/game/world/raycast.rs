@@ -0,0 +1,154 @@
+use cgmath::{InnerSpace, Vector3};
+
+use super::World;
+use crate::game::chunk::block::Block;
+
+pub struct RaycastHit<'a> {
+    pub position: Vector3<i32>,
+    // Points out of the face the ray went in through. Zero if the ray started inside the block.
+    pub normal: Vector3<i32>,
+    // From the ray's origin to where it hit the block.
+    pub distance: f32,
+    pub state: u32,
+    pub block: &'a Block,
+}
+
+impl World {
+
+    // Finds the first block that isn't air along a ray, no further than 'max_distance' from its origin.
+    // Steps from block to block through every one the ray passes, always crossing whichever boundary it reaches first
+    // (Amanatides and Woo's "A Fast Voxel Traversal Algorithm"). Chunks that aren't loaded are passed through.
+    pub fn raycast(&self, origin: Vector3<f32>, direction: Vector3<f32>, max_distance: f32) -> Option<RaycastHit<'_>> {
+
+        if direction.magnitude2() == 0.0 { return None; }
+        let direction = direction.normalize();
+
+        // Blocks span -1 on the z axis from their position, so on z a block is one further along than its floor.
+        let cell_offset = [0, 0, 1];
+        let mut position = Vector3::new(origin.x.floor() as i32, origin.y.floor() as i32, origin.z.floor() as i32 + 1);
+
+        // Per axis, which way the ray steps, how far along it the next boundary is, and how far apart boundaries are.
+        let mut step = [0; 3];
+        let mut next = [f32::INFINITY; 3];
+        let mut delta = [f32::INFINITY; 3];
+        for axis in 0..3 {
+            let low = (position[axis] - cell_offset[axis]) as f32;
+            if direction[axis] > 0.0 {
+                step[axis] = 1;
+                next[axis] = (low + 1.0 - origin[axis]) / direction[axis];
+                delta[axis] = 1.0 / direction[axis];
+            }
+            else if direction[axis] < 0.0 {
+                step[axis] = -1;
+                next[axis] = (low - origin[axis]) / direction[axis];
+                delta[axis] = -1.0 / direction[axis];
+            }
+        }
+
+        let mut normal = Vector3::new(0, 0, 0);
+        let mut distance = 0.0;
+
+        loop {
+
+            if let Some(state) = self.get_state_at(position) {
+                if state != 0 {
+                    let block = self.block_list.get_block_from_state(state)?;
+                    return Some(RaycastHit { position, normal, distance, state, block });
+                }
+            }
+
+            let axis = if next[0] < next[1] {
+                if next[0] < next[2] { 0 } else { 2 }
+            }
+            else if next[1] < next[2] { 1 } else { 2 };
+
+            distance = next[axis];
+            if distance > max_distance { return None; }
+
+            position[axis] += step[axis];
+            next[axis] += delta[axis];
+            normal = Vector3::new(0, 0, 0);
+            normal[axis] = -step[axis];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::tests::{empty_world, insert_empty_chunk};
+
+    // A world of empty chunks around the origin with stone at the given blocks.
+    fn world_with_stone(blocks: &[Vector3<i32>]) -> World {
+        let mut world = empty_world();
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    insert_empty_chunk(&mut world, Vector3::new(x, y, z));
+                }
+            }
+        }
+        let stone = world.block_list.get_block("stone").unwrap().default_state;
+        for block in blocks.iter() {
+            assert!(world.set_block_at(*block, stone));
+        }
+        world
+    }
+
+    fn assert_hit(hit: Option<RaycastHit>, position: Vector3<i32>, normal: Vector3<i32>, distance: f32) {
+        let hit = hit.expect("the ray didn't hit anything");
+        assert_eq!(hit.position, position);
+        assert_eq!(hit.normal, normal);
+        assert!((hit.distance - distance).abs() < 0.0001, "hit at {}, expected {}", hit.distance, distance);
+        assert_eq!(hit.block.name, "stone");
+    }
+
+    #[test]
+    fn hitting_a_block_in_the_same_chunk() {
+        // Stone filling x = 5..6, y = 2..3, z = 2..3.
+        let world = world_with_stone(&[Vector3::new(5, 2, 3)]);
+        let hit = world.raycast(Vector3::new(1.5, 2.5, 2.5), Vector3::new(1.0, 0.0, 0.0), 8.0);
+        assert_hit(hit, Vector3::new(5, 2, 3), Vector3::new(-1, 0, 0), 3.5);
+    }
+
+    #[test]
+    fn hitting_a_block_across_a_chunk_boundary() {
+        let world = world_with_stone(&[Vector3::new(17, 2, 3)]);
+        let hit = world.raycast(Vector3::new(14.5, 2.5, 2.5), Vector3::new(1.0, 0.0, 0.0), 8.0);
+        assert_hit(hit, Vector3::new(17, 2, 3), Vector3::new(-1, 0, 0), 2.5);
+
+        // Down through the floor of one chunk into the one below.
+        let world = world_with_stone(&[Vector3::new(3, -2, 3)]);
+        let hit = world.raycast(Vector3::new(3.5, 1.5, 2.5), Vector3::new(0.0, -1.0, 0.0), 8.0);
+        assert_hit(hit, Vector3::new(3, -2, 3), Vector3::new(0, 1, 0), 2.5);
+    }
+
+    #[test]
+    fn hitting_blocks_at_negative_positions() {
+        // Blocks span -1 on z from their position, so stone at grid z -5 fills z = -6..-5.
+        let world = world_with_stone(&[Vector3::new(-3, -2, -5)]);
+
+        // From above it on z, hitting the face at z = -5.
+        let hit = world.raycast(Vector3::new(-2.5, -1.5, -1.5), Vector3::new(0.0, 0.0, -1.0), 8.0);
+        assert_hit(hit, Vector3::new(-3, -2, -5), Vector3::new(0, 0, 1), 3.5);
+
+        // From below it on z, hitting the face at z = -6.
+        let hit = world.raycast(Vector3::new(-2.5, -1.5, -8.5), Vector3::new(0.0, 0.0, 1.0), 8.0);
+        assert_hit(hit, Vector3::new(-3, -2, -5), Vector3::new(0, 0, -1), 2.5);
+
+        // Along x, a ray at z = -5.5 is in the block's row.
+        let hit = world.raycast(Vector3::new(-7.5, -1.5, -5.5), Vector3::new(1.0, 0.0, 0.0), 8.0);
+        assert_hit(hit, Vector3::new(-3, -2, -5), Vector3::new(-1, 0, 0), 4.5);
+
+        // Starting inside the block there's no face to have gone through.
+        let hit = world.raycast(Vector3::new(-2.5, -1.5, -5.5), Vector3::new(1.0, 0.0, 0.0), 8.0);
+        assert_hit(hit, Vector3::new(-3, -2, -5), Vector3::new(0, 0, 0), 0.0);
+    }
+
+    #[test]
+    fn missing_a_block_past_max_distance() {
+        let world = world_with_stone(&[Vector3::new(5, 2, 3)]);
+        assert!(world.raycast(Vector3::new(1.5, 2.5, 2.5), Vector3::new(1.0, 0.0, 0.0), 3.0).is_none());
+        assert!(world.raycast(Vector3::new(1.5, 2.5, 2.5), Vector3::new(1.0, 0.0, 0.0), 3.5).is_some());
+    }
+}
@@ -0,0 +1,59 @@
+use std::collections::{HashSet, VecDeque};
+
+use cgmath::{MetricSpace, Vector3};
+
+use super::World;
+use crate::game::{DESTROY_DISTANCE, camera::Camera, chunk::{CHUNK_SIZE, Chunk, Face}};
+
+impl World {
+
+    // Every chunk that might be seen from the camera, found by walking out from the camera's chunk
+    // and only passing through a chunk between faces its connectivity says are connected.
+    // The walk never turns back toward the camera, and doesn't leave the view.
+    // Unloaded chunks are walked through as if they were empty, so what's behind them isn't hidden.
+    pub fn visible_chunks(&self, camera: &Camera) -> HashSet<Vector3<i32>> {
+
+        let camera_block = Vector3::new(camera.position.x.floor() as i32, camera.position.y.floor() as i32, camera.position.z.ceil() as i32);
+        let (start, _) = Chunk::locate(camera_block);
+        let camera_chunk = camera.position / CHUNK_SIZE as f32;
+        let radius = f32::sqrt(3.0) * CHUNK_SIZE as f32 / 2.0;
+
+        let mut visible = HashSet::new();
+        let mut queue = VecDeque::new();
+
+        // Each step carries the face it came in through, and every direction walked so far.
+        visible.insert(start);
+        queue.push_back((start, None, 0u8));
+
+        while let Some((position, entered, walked)) = queue.pop_front() {
+
+            let connectivity = match self.chunks.get(&position) {
+                Some(chunk) => chunk.connectivity,
+                None => [Chunk::ALL_FACES; 6],
+            };
+
+            for (i, face) in Face::ALL.iter().enumerate() {
+
+                // Faces come in opposite pairs in Face::ALL.
+                let opposite = i ^ 1;
+                if walked & (1 << opposite) != 0 { continue; }
+
+                if let Some(entered) = entered {
+                    if connectivity[entered] & (1 << i) == 0 { continue; }
+                }
+
+                let next = position + face.direction();
+                if visible.contains(&next) { continue; }
+
+                let center = Vector3::new(next.x as f32 + 0.5, next.y as f32 + 0.5, next.z as f32 + 0.5);
+                if center.distance(camera_chunk) > DESTROY_DISTANCE as f32 { continue; }
+                if !camera.frustum.sphere_intersection(center * CHUNK_SIZE as f32, radius) { continue; }
+
+                visible.insert(next);
+                queue.push_back((next, Some(opposite), walked | (1 << i)));
+            }
+        }
+
+        visible
+    }
+}
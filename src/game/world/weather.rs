@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use cgmath::Vector3;
+
+use super::World;
+use crate::game::{camera::Camera, chunk::{CHUNK_SIZE, Chunk}, color::Color, renderer::{Renderer, precipitation::{HEIGHTMAP_SIZE, HEIGHTMAP_UPDATE_INTERVAL, PARTICLE_COUNT}}};
+
+// How quickly weather fades in and out, in intensity per second.
+const FADE_SPEED: f32 = 0.2;
+
+// How quickly a lightning flash fades, in brightness per second.
+const FLASH_FADE_SPEED: f32 = 4.0;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Weather {
+    Clear,
+    Rain,
+    Thunder,
+    Snow,
+}
+
+impl Weather {
+
+    // How long this weather lasts, in seconds, as a range to pick from.
+    fn duration_range(&self) -> (f64, f64) {
+        match self {
+            Weather::Clear => (90.0, 240.0),
+            Weather::Rain => (40.0, 120.0),
+            Weather::Thunder => (30.0, 90.0),
+            Weather::Snow => (40.0, 120.0),
+        }
+    }
+
+    // How much the sky and light are darkened at full intensity, 0..1.
+    fn darkness(&self) -> f32 {
+        match self {
+            Weather::Clear => 0.0,
+            Weather::Rain => 0.45,
+            Weather::Thunder => 0.7,
+            Weather::Snow => 0.3,
+        }
+    }
+}
+
+// Weather changes on its own, always passing through clear skies between anything else.
+// Everything random about it comes from the world's seed.
+pub struct WeatherState {
+    pub current: Weather,
+    // Seconds until the weather changes.
+    pub time_left: f64,
+    // 0..1, how far the current weather has faded in.
+    pub intensity: f32,
+    // 0..1, brightness of the latest lightning strike.
+    pub flash: f32,
+    rng: StdRng,
+    // Kept apart since it's rolled every frame, so how often frames come doesn't change the weather picked.
+    lightning_rng: StdRng,
+}
+
+impl WeatherState {
+
+    pub fn new(seed: u64) -> WeatherState {
+        let mut weather = WeatherState {
+            current: Weather::Clear,
+            time_left: 0.0,
+            intensity: 0.0,
+            flash: 0.0,
+            rng: StdRng::seed_from_u64(seed),
+            lightning_rng: StdRng::seed_from_u64(seed.wrapping_add(1)),
+        };
+        weather.time_left = weather.pick_duration(Weather::Clear);
+        weather
+    }
+
+    fn pick_duration(&mut self, weather: Weather) -> f64 {
+        let (min, max) = weather.duration_range();
+        self.rng.gen_range(min..max)
+    }
+
+    fn pick_next(&mut self) -> Weather {
+        if self.current != Weather::Clear {
+            return Weather::Clear;
+        }
+
+        match self.rng.gen_range(0..4) {
+            0 | 1 => Weather::Rain,
+            2 => Weather::Thunder,
+            _ => Weather::Snow,
+        }
+    }
+
+    pub fn update(&mut self, delta: f32) {
+
+        self.time_left -= delta as f64;
+
+        // The old weather fades out completely before the next one starts fading in.
+        if self.time_left <= 0.0 {
+            if self.current == Weather::Clear || self.intensity <= 0.0 {
+                self.current = self.pick_next();
+                self.time_left = self.pick_duration(self.current);
+            }
+            self.intensity = (self.intensity - FADE_SPEED * delta).max(0.0);
+        }
+        else if self.current != Weather::Clear {
+            self.intensity = (self.intensity + FADE_SPEED * delta).min(1.0);
+        }
+
+        self.flash = (self.flash - FLASH_FADE_SPEED * delta).max(0.0);
+        if self.current == Weather::Thunder && self.intensity > 0.5 && self.lightning_rng.gen_bool((0.08 * delta as f64).min(1.0)) {
+            self.flash = 1.0;
+        }
+    }
+
+    pub fn darkness(&self) -> f32 {
+        self.current.darkness() * self.intensity
+    }
+}
+
+impl World {
+
+    // Clouds the sky over and dims the light with bad weather, and flashes both with lightning.
+    pub fn apply_weather(&self, color: Color) -> Color {
+
+        let gray = (color.r + color.g + color.b) / 3.0 * 0.5;
+        let darkened = Color::lerp(color, Color::from_rgb(gray, gray, gray), self.weather.darkness());
+        Color::lerp(darkened, Color::from_rgb(1.0, 1.0, 1.0), self.weather.flash * 0.7)
+    }
+
+    // Tells the renderer what to drop around the camera, and keeps its heightmap of the nearby columns
+    // up to date so precipitation stops at roofs.
+    pub fn update_precipitation(&mut self, renderer: &mut Renderer, camera: &Camera, delta: f32) {
+
+        let precipitation = &mut renderer.precipitation;
+
+        // Color, fall speed, size and sway.
+        let (color, fall_speed, size, sway) = match self.weather.current {
+            Weather::Clear => ([1.0, 1.0, 1.0], 0.0, [0.0, 0.0], 0.0),
+            Weather::Rain => ([0.6, 0.7, 0.9], 14.0, [0.04, 0.7], 0.0),
+            Weather::Thunder => ([0.55, 0.6, 0.8], 18.0, [0.04, 0.9], 0.0),
+            Weather::Snow => ([1.0, 1.0, 1.0], 1.5, [0.12, 0.12], 0.4),
+        };
+
+        precipitation.data.color = color;
+        precipitation.data.fall_speed = fall_speed;
+        precipitation.data.particle_size = size;
+        precipitation.data.sway = sway;
+        precipitation.data.camera_position = camera.position.into();
+        precipitation.data.time = (self.time % 1000.0) as f32;
+        precipitation.particle_count = if self.weather.current == Weather::Clear { 0 } else { (PARTICLE_COUNT as f32 * self.weather.intensity) as u32 };
+
+        precipitation.time_since_heightmap += delta;
+        if precipitation.particle_count == 0 || precipitation.time_since_heightmap < HEIGHTMAP_UPDATE_INTERVAL {
+            return;
+        }
+        precipitation.time_since_heightmap = 0.0;
+
+        // Roofs and ground can be any height, so each column is looked through from the top of its highest loaded chunk
+        // to the bottom of its lowest. Columns with nothing loaded in them never block anything.
+        let mut loaded_columns: HashMap<(i32, i32), (i32, i32)> = HashMap::new();
+        for position in self.chunks.keys() {
+            let (lowest, highest) = loaded_columns.entry((position.x, position.z)).or_insert((position.y, position.y));
+            *lowest = (*lowest).min(position.y);
+            *highest = (*highest).max(position.y);
+        }
+
+        let half = HEIGHTMAP_SIZE as i32 / 2;
+        let origin = Vector3::new(camera.position.x.floor() as i32 - half, 0, camera.position.z.ceil() as i32 - half);
+
+        let mut heights = Vec::with_capacity((HEIGHTMAP_SIZE * HEIGHTMAP_SIZE) as usize);
+        for z in 0..HEIGHTMAP_SIZE as i32 {
+            for x in 0..HEIGHTMAP_SIZE as i32 {
+                let (chunk_position, _) = Chunk::locate(Vector3::new(origin.x + x, 0, origin.z + z));
+                let highest = loaded_columns.get(&(chunk_position.x, chunk_position.z)).and_then(|(lowest, highest)| {
+                    let (top, bottom) = ((highest + 1) * CHUNK_SIZE as i32 - 1, lowest * CHUNK_SIZE as i32);
+                    self.get_highest_block(origin.x + x, origin.z + z, top, bottom)
+                });
+
+                let height = match highest {
+                    Some(y) => (y + 1) as f32,
+                    None => -1000.0,
+                };
+                heights.push(height);
+            }
+        }
+
+        renderer.precipitation.write_heightmap(&renderer.queue, [origin.x, origin.z], &heights);
+    }
+}
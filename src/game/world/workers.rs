@@ -0,0 +1,253 @@
+use std::{collections::{HashMap, HashSet}, sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}, mpsc::{self, Receiver, Sender}}, thread, time::{Duration, Instant}};
+
+use bracket_noise::prelude::FastNoise;
+use cgmath::Vector3;
+
+use super::{World, climate::Climate, lod::LodRegion};
+use crate::game::{CHUNKS_GEN_PER_FRAME, CHUNKS_MESHED_PER_FRAME, camera::Camera, chunk::{CHUNK_SIZE, Chunk, ChunkSnapshot, MeshParts, block::{BlockList, BlockTable}}, color::Color, profiler::{self, Phase}, renderer::{Renderer, vertex::ChunkInstance}};
+
+// How many chunks can be waiting on or being generated at once.
+// Keeps the queue moving toward whatever's closest instead of handing everything off at once.
+const MAX_GENERATING: usize = 64;
+
+// How long a frame can spend lighting chunks that have come back from the workers. The rest wait in the channel,
+// still counted as generating so they aren't queued again.
+const LIGHTING_BUDGET: Duration = Duration::from_millis(2);
+
+pub enum Job {
+    // The chunk to generate, and whether it's been cancelled, checked before it's started.
+    Generate(Vector3<i32>, Arc<AtomicBool>),
+    // The snapshot to mesh, and which job this is for the chunk. Its tints are sampled by the worker.
+    Mesh(Box<ChunkSnapshot>, u64),
+    // A far off region to generate and mesh in one go, its level of detail and position.
+    Lod(u32, Vector3<i32>),
+}
+
+// Threads that generate and mesh chunks in the background, so the main thread never waits on them.
+// They live as long as the world does, and stop once it's dropped.
+pub struct Workers {
+    pub jobs: Sender<Job>,
+    pub generated: Receiver<Chunk>,
+    // Along with the chunk's connectivity, worked out at the same time.
+    pub meshed: Receiver<(Vector3<i32>, u64, MeshParts, [u8; 6])>,
+    // Chunks handed off for generation that haven't been applied yet, and their cancel flags.
+    pub generating: HashMap<Vector3<i32>, Arc<AtomicBool>>,
+
+    pub lod_meshed: Receiver<(u32, Vector3<i32>, MeshParts)>,
+    // How long each part of every job took, on which worker and when it started, see profiler::record_worker.
+    pub timings: Receiver<(usize, Phase, Instant, Duration)>,
+    // Regions handed off that haven't been applied yet, by level and position.
+    pub lod_generating: HashSet<(u32, Vector3<i32>)>,
+}
+
+impl Workers {
+
+    pub fn new(noise: Arc<FastNoise>, climate: Arc<Climate>, block_list: Arc<BlockList>, table: Arc<BlockTable>) -> Workers {
+
+        let (jobs, job_receiver) = mpsc::channel::<Job>();
+        let (generated_sender, generated) = mpsc::channel();
+        let (meshed_sender, meshed) = mpsc::channel();
+        let (lod_sender, lod_meshed) = mpsc::channel();
+        let (timings_sender, timings) = mpsc::channel();
+
+        // Leaves a core for the main thread.
+        let count = thread::available_parallelism().map_or(1, |n| n.get()).saturating_sub(1).max(1);
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        for worker in 1..=count {
+
+            let job_receiver = job_receiver.clone();
+            let generated_sender = generated_sender.clone();
+            let meshed_sender = meshed_sender.clone();
+            let lod_sender = lod_sender.clone();
+            let timings_sender = timings_sender.clone();
+            let noise = noise.clone();
+            let climate = climate.clone();
+            let block_list = block_list.clone();
+            let table = table.clone();
+
+            thread::spawn(move || {
+                loop {
+
+                    // The lock is only held while waiting, never while working.
+                    let job = match job_receiver.lock().unwrap().recv() {
+                        Ok(job) => job,
+                        Err(_) => return,
+                    };
+
+                    let timed = |phase: Phase, start: Instant| timings_sender.send((worker, phase, start, start.elapsed())).is_ok();
+
+                    // Sending only fails once the world is gone, so there's nothing left to do.
+                    let sent = match job {
+                        Job::Generate(position, cancelled) => {
+                            if cancelled.load(Ordering::Relaxed) { continue; }
+                            let start = Instant::now();
+                            let chunk = Chunk::new(position, &noise, &block_list);
+                            timed(Phase::Generation, start) && generated_sender.send(chunk).is_ok()
+                        },
+                        Job::Mesh(mut snapshot, id) => {
+                            let start = Instant::now();
+                            snapshot.tints = climate.get_tints(snapshot.position, 1);
+                            let parts = snapshot.generate_mesh_parts();
+                            let connectivity = snapshot.connectivity();
+                            timed(Phase::Meshing, start) && meshed_sender.send((snapshot.position, id, parts, connectivity)).is_ok()
+                        },
+                        Job::Lod(level, position) => {
+                            let start = Instant::now();
+                            let grid = Chunk::generate_grid(position, LodRegion::size(level), &noise, &block_list);
+                            let generated = timed(Phase::Generation, start);
+
+                            let start = Instant::now();
+                            let tints = climate.get_tints(position, LodRegion::size(level));
+                            let parts = ChunkSnapshot::lod(position, &grid, tints, table.clone()).generate_mesh_parts();
+                            generated && timed(Phase::Meshing, start) && lod_sender.send((level, position, parts)).is_ok()
+                        },
+                    };
+                    if !sent { return; }
+                }
+            });
+        }
+
+        Workers { jobs, generated, meshed, generating: HashMap::new(), lod_meshed, lod_generating: HashSet::new(), timings }
+    }
+
+    pub fn generate(&mut self, position: Vector3<i32>) {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.generating.insert(position, cancelled.clone());
+        self.jobs.send(Job::Generate(position, cancelled)).unwrap();
+    }
+
+    // Cancels generating every chunk 'should_cancel' returns true for. Ones already started still finish,
+    // but they're thrown away when they come back.
+    pub fn cancel_generation<F: FnMut(Vector3<i32>) -> bool>(&mut self, mut should_cancel: F) {
+        self.generating.retain(|position, cancelled| {
+            if should_cancel(*position) {
+                cancelled.store(true, Ordering::Relaxed);
+                false
+            }
+            else {
+                true
+            }
+        });
+    }
+}
+
+impl World {
+
+    // Copies out the blocks and light needed to mesh a chunk. None if it isn't loaded.
+    // Tints are left white, sampling them is left to whoever meshes it, see Climate::get_tints.
+    pub fn snapshot_chunk(&self, position: Vector3<i32>) -> Option<ChunkSnapshot> {
+
+        self.chunks.get(&position)?;
+
+        let tints = [[Color::from_rgb(1.0, 1.0, 1.0); CHUNK_SIZE]; CHUNK_SIZE];
+        let mut snapshot = ChunkSnapshot::new(position, tints, self.smooth_lighting, self.block_table.clone());
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    let offset = Vector3::new(x, y, z);
+                    if let Some(chunk) = self.chunks.get(&(position + offset)) {
+                        snapshot.copy_chunk(offset, &chunk.grid, &chunk.light);
+                    }
+                }
+            }
+        }
+
+        Some(snapshot)
+    }
+
+    // Hands queued chunks and chunks needing a new mesh off to the workers, then applies a limited number
+    // of whatever they've finished since last frame. Never waits on them.
+    pub fn update_chunks(&mut self, renderer: &mut Renderer, camera: &Camera) {
+
+        // Whatever the workers have timed since last frame.
+        for (worker, phase, start, duration) in self.workers.timings.try_iter() {
+            profiler::record_worker(worker, phase, start, duration);
+        }
+
+        let queueing = profiler::scope(Phase::Queueing);
+        self.update_chunk_queue(camera);
+
+        // Generation, most important first.
+        while self.workers.generating.len() < MAX_GENERATING {
+            match self.chunk_queue.pop() {
+                Some(position) => self.workers.generate(position),
+                None => break,
+            }
+        }
+
+        drop(queueing);
+
+        // Meshing. A chunk edited again before its mesh comes back just gets another job,
+        // the older one is ignored when it arrives.
+        let snapshots = profiler::scope(Phase::Snapshots);
+        let mut to_mesh = Vec::new();
+        for (position, chunk) in self.chunks.iter_mut() {
+            if chunk.should_regen_mesh {
+                chunk.should_regen_mesh = false;
+                self.next_mesh_job += 1;
+                chunk.mesh_job = self.next_mesh_job;
+                to_mesh.push((*position, chunk.mesh_job));
+            }
+        }
+
+        for (position, id) in to_mesh {
+            if let Some(snapshot) = self.snapshot_chunk(position) {
+                self.workers.jobs.send(Job::Mesh(Box::new(snapshot), id)).unwrap();
+            }
+        }
+        drop(snapshots);
+
+        let lighting = profiler::scope(Phase::Lighting);
+        let lighting_start = Instant::now();
+        for _ in 0..CHUNKS_GEN_PER_FRAME {
+
+            if lighting_start.elapsed() > LIGHTING_BUDGET { break; }
+            let chunk = match self.workers.generated.try_recv() {
+                Ok(chunk) => chunk,
+                Err(_) => break,
+            };
+
+            // Anything no longer being waited on was cancelled.
+            let p = chunk.position;
+            if self.workers.generating.remove(&p).is_none() || self.chunks.contains_key(&p) { continue; }
+
+            self.chunks.insert(p, chunk);
+            self.light_chunk(p);
+
+            // Every chunk touching this one, corners included, samples it for culling, lighting and occlusion.
+            for x in -1..=1 {
+                for y in -1..=1 {
+                    for z in -1..=1 {
+                        if let Some(chunk) = self.chunks.get_mut(&(p + Vector3::new(x, y, z))) {
+                            chunk.should_regen_mesh = true;
+                        }
+                    }
+                }
+            }
+        }
+
+        drop(lighting);
+
+        // Uploading has to be done here, the workers only build the vertices.
+        let _uploads = profiler::scope(Phase::Uploads);
+        for (position, id, parts, connectivity) in self.workers.meshed.try_iter().take(CHUNKS_MESHED_PER_FRAME as usize) {
+
+            let chunk = match self.chunks.get_mut(&position) {
+                Some(chunk) => chunk,
+                None => continue,
+            };
+            if chunk.mesh_job != id { continue; }
+
+            chunk.meshed = true;
+            chunk.connectivity = connectivity;
+
+            let instance = ChunkInstance { origin: (position * CHUNK_SIZE as i32).cast::<f32>().unwrap().into(), scale: 1.0 };
+            let arena = &mut renderer.chunk_arena;
+            chunk.mesh = if parts.vertices.is_empty() { None }
+                else { Some(arena.upload(&renderer.device, &renderer.queue, &parts.vertices, instance)) };
+            chunk.translucent_mesh = if parts.translucent_vertices.is_empty() { None }
+                else { Some(arena.upload(&renderer.device, &renderer.queue, &parts.translucent_vertices, instance)) };
+        }
+    }
+}